@@ -1,12 +1,14 @@
 mod naive_compressor;
+mod vbyte_compressor;
 
 pub use compressor::naive_compressor::NaiveCompressor;
+pub use compressor::vbyte_compressor::VByteCompressor;
 use utils::ring_buffer::BiasedRingBuffer;
 use index::posting::Posting;
 use page_manager::Block;
 
 pub trait Compressor {
-    fn compress(&mut BiasedRingBuffer<Posting>) -> Option<Block>;
-    fn force_compress(&mut BiasedRingBuffer<Posting>) -> Block;
-    fn decompress(Block, &mut BiasedRingBuffer<Posting>);
+    fn compress<const N: usize>(data: &mut BiasedRingBuffer<Posting, N>) -> Option<Block>;
+    fn force_compress<const N: usize>(data: &mut BiasedRingBuffer<Posting, N>) -> Block;
+    fn decompress<const N: usize>(data: Block, target: &mut BiasedRingBuffer<Posting, N>);
 }
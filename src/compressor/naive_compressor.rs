@@ -1,5 +1,3 @@
-use std::mem;
-
 use utils::ring_buffer::{BiasedRingBuffer};
 use utils::Baseable;
 use index::posting::{Posting, DocId};
@@ -7,19 +5,21 @@ use page_manager::{BLOCKSIZE, Block};
 use compressor::Compressor;
 
 
+/// Stores every posting as a little endian u32, relative to the buffer's
+/// base. Unused space at the end of a block is padded with `DocId::none()`.
+#[derive(Debug)]
 pub struct NaiveCompressor;
 
 impl Compressor for NaiveCompressor {
-    fn compress(data: &mut BiasedRingBuffer<Posting>) -> Option<Block>
+    fn compress<const N: usize>(data: &mut BiasedRingBuffer<Posting, N>) -> Option<Block>
         where Posting: for<'x> Baseable<&'x Posting>
     {
         if data.count() >= BLOCKSIZE / 4 {
             // Enough in there to fill the block
             let mut block = [0u8; BLOCKSIZE];
             for i in 0..BLOCKSIZE / 4 {
-                block[i * 4..(i * 4) + 4].copy_from_slice(unsafe {
-                    &mem::transmute::<Posting, [u8; 4]>(data.pop_front_biased().unwrap())
-                });
+                let posting = data.pop_front_biased().unwrap();
+                block[i * 4..(i * 4) + 4].copy_from_slice(&(posting.0).0.to_le_bytes());
             }
             Some(Block(block))
         } else {
@@ -27,20 +27,18 @@ impl Compressor for NaiveCompressor {
         }
     }
 
-    fn force_compress(data: &mut BiasedRingBuffer<Posting>) -> Block {
+    fn force_compress<const N: usize>(data: &mut BiasedRingBuffer<Posting, N>) -> Block {
         let mut block = [0u8; BLOCKSIZE];
         for i in 0..BLOCKSIZE / 4 {
             let posting = data.pop_front_biased().unwrap_or_else(|| Posting(DocId::none()));
-            block[i * 4..(i * 4) + 4]
-                .copy_from_slice(unsafe { &mem::transmute::<Posting, [u8; 4]>(posting) });
+            block[i * 4..(i * 4) + 4].copy_from_slice(&(posting.0).0.to_le_bytes());
         }
         Block(block)
     }
 
-    fn decompress(data: Block, target: &mut BiasedRingBuffer<Posting>) {
-        let nums: [u32; BLOCKSIZE / 4] = unsafe { mem::transmute(data) };
-        for num in &nums {
-            let did = DocId(*num);
+    fn decompress<const N: usize>(data: Block, target: &mut BiasedRingBuffer<Posting, N>) {
+        for bytes in data.0.chunks(4) {
+            let did = DocId(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
             if did != DocId::none() {
                 target.push_back_biased(Posting(did));
             } else {
@@ -55,7 +53,7 @@ impl Compressor for NaiveCompressor {
 mod tests {
     use utils::ring_buffer::BiasedRingBuffer;
     use index::posting::{DocId, Posting};
-    use page_manager::{BLOCKSIZE};
+    use page_manager::{Block, BLOCKSIZE};
     use compressor::Compressor;
 
     use super::NaiveCompressor;
@@ -101,4 +99,20 @@ mod tests {
         assert_eq!(buffer.pop_front(), None);
     }
 
+    #[test]
+    fn little_endian() {
+        let mut buffer = BiasedRingBuffer::<Posting>::new();
+        buffer.push_back(Posting(DocId(1)));
+        buffer.push_back(Posting(DocId(0x0403_0201)));
+        let block = NaiveCompressor::force_compress(&mut buffer);
+        assert_eq!(&block.0[..12], &[1, 0, 0, 0, 1, 2, 3, 4, 255, 255, 255, 255]);
+
+        // Hand crafted block
+        let mut fixture = [255; BLOCKSIZE];
+        fixture[..8].copy_from_slice(&[7, 0, 0, 0, 0, 1, 0, 0]);
+        NaiveCompressor::decompress(Block(fixture), &mut buffer);
+        assert_eq!(buffer.pop_front(), Some(Posting(DocId(7))));
+        assert_eq!(buffer.pop_front(), Some(Posting(DocId(256))));
+        assert_eq!(buffer.pop_front(), None);
+    }
 }
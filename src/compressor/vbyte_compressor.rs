@@ -0,0 +1,194 @@
+use utils::ring_buffer::BiasedRingBuffer;
+use utils::vbyte::{vbyte_encode, vbyte_len, VByteDecoder};
+use index::posting::{Posting, DocId};
+use page_manager::{BLOCKSIZE, Block};
+use compressor::Compressor;
+
+/// Bytes of a block available for postings. The first byte holds the number
+/// of postings in the block, so no padding sentinel is needed.
+const PAYLOAD: usize = BLOCKSIZE - 1;
+
+/// `Listing::add` pushes up to this many postings between two calls to
+/// `compress`. They must always fit into the posting buffer.
+const PUSHES_BETWEEN_COMPRESS: usize = 16;
+
+/// Stores the first posting of a block relative to the buffer's base and
+/// every following posting as delta to its predecessor, variable byte
+/// encoded. Runs of small deltas fit up to 48 postings into a block, limited
+/// by the capacity of the posting buffer rather than by `PAYLOAD`.
+#[derive(Debug)]
+pub struct VByteCompressor;
+
+impl VByteCompressor {
+    /// How many postings from the front of `data` fit into one block and
+    /// whether these are all postings in `data`.
+    /// Small deltas would fit more postings into a block than the buffer can
+    /// hold. So blocks are capped to leave room for the next pushes.
+    fn fitting<const N: usize>(data: &BiasedRingBuffer<Posting, N>) -> (usize, bool) {
+        let max_count = data.capacity() - PUSHES_BETWEEN_COMPRESS;
+        let mut bytes = 0;
+        let mut count = 0;
+        let mut last = 0;
+        for posting in data.iter_biased() {
+            if count == max_count {
+                return (count, false);
+            }
+            let value = (posting.0).0;
+            bytes += vbyte_len((value - last) as usize);
+            if bytes > PAYLOAD {
+                return (count, false);
+            }
+            count += 1;
+            last = value;
+        }
+        (count, true)
+    }
+
+    fn encode<const N: usize>(data: &mut BiasedRingBuffer<Posting, N>, count: usize) -> Block {
+        let mut block = [0u8; BLOCKSIZE];
+        block[0] = count as u8;
+        let mut ptr = 1;
+        let mut last = 0;
+        for _ in 0..count {
+            let value = (data.pop_front_biased().unwrap().0).0;
+            for byte in vbyte_encode((value - last) as usize) {
+                block[ptr] = byte;
+                ptr += 1;
+            }
+            last = value;
+        }
+        Block(block)
+    }
+}
+
+impl Compressor for VByteCompressor {
+    fn compress<const N: usize>(data: &mut BiasedRingBuffer<Posting, N>) -> Option<Block> {
+        match VByteCompressor::fitting(data) {
+            // Everything fits. Wait for more postings to fill the block
+            (_, true) => None,
+            (count, false) => Some(VByteCompressor::encode(data, count)),
+        }
+    }
+
+    /// Compresses as many postings as fit into a block.
+    /// After `compress` returned `None` these are all remaining postings.
+    fn force_compress<const N: usize>(data: &mut BiasedRingBuffer<Posting, N>) -> Block {
+        let (count, _) = VByteCompressor::fitting(data);
+        VByteCompressor::encode(data, count)
+    }
+
+    fn decompress<const N: usize>(data: Block, target: &mut BiasedRingBuffer<Posting, N>) {
+        let count = data.0[0] as usize;
+        let mut value = 0;
+        for delta in VByteDecoder::new(data.0[1..].iter().cloned()).take(count) {
+            value += delta as u32;
+            target.push_back_biased(Posting(DocId(value)));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use utils::ring_buffer::{BiasedRingBuffer, SIZE};
+    use index::posting::{DocId, Posting};
+    use compressor::{Compressor, NaiveCompressor};
+
+    use super::{VByteCompressor, PAYLOAD, PUSHES_BETWEEN_COMPRESS};
+
+    const MAX_COUNT: usize = SIZE - PUSHES_BETWEEN_COMPRESS;
+
+    #[test]
+    fn compress() {
+        let mut buffer = BiasedRingBuffer::<Posting>::new();
+        assert_eq!(VByteCompressor::compress(&mut buffer), None);
+        for i in 0..MAX_COUNT {
+            buffer.push_back(Posting(DocId(i as u32)));
+        }
+        // Leaves exactly enough room for the next pushes
+        assert_eq!(VByteCompressor::compress(&mut buffer), None);
+        buffer.push_back(Posting(DocId(MAX_COUNT as u32)));
+        assert!(VByteCompressor::compress(&mut buffer).is_some());
+        assert_eq!(buffer.count(), 1);
+    }
+
+    #[test]
+    fn compress_full_payload() {
+        let mut buffer = BiasedRingBuffer::<Posting>::new();
+        // Two bytes per delta. The payload is full before the count cap
+        for i in 0..PAYLOAD as u32 / 2 + 1 {
+            buffer.push_back(Posting(DocId(i * 200)));
+        }
+        assert_eq!(VByteCompressor::compress(&mut buffer), None);
+        buffer.push_back(Posting(DocId(100_000)));
+        assert!(VByteCompressor::compress(&mut buffer).is_some());
+        assert_eq!(buffer.count(), 1);
+    }
+
+    #[test]
+    fn decompress() {
+        let mut buffer = BiasedRingBuffer::<Posting>::new();
+        for i in 0..MAX_COUNT + 1 {
+            buffer.push_back(Posting(DocId(i as u32 * 3)));
+        }
+        let block = VByteCompressor::compress(&mut buffer).unwrap();
+        assert_eq!(buffer.count(), 1);
+        buffer.flush();
+        VByteCompressor::decompress(block, &mut buffer);
+        for i in 0..MAX_COUNT {
+            assert_eq!(buffer.pop_front().unwrap(), Posting(DocId(i as u32 * 3)));
+        }
+        assert_eq!(buffer.pop_front(), None);
+    }
+
+    #[test]
+    fn force_compress() {
+        let mut buffer = BiasedRingBuffer::<Posting>::new();
+        assert_eq!(VByteCompressor::compress(&mut buffer), None);
+        buffer.push_back(Posting(DocId(0)));
+        buffer.push_back(Posting(DocId(1)));
+        buffer.push_back(Posting(DocId(1_000_000)));
+        buffer.push_back(Posting(DocId(DocId::none().0 - 1)));
+        assert_eq!(VByteCompressor::compress(&mut buffer), None);
+        let block = VByteCompressor::force_compress(&mut buffer);
+        assert_eq!(buffer.count(), 0);
+        VByteCompressor::decompress(block, &mut buffer);
+        assert_eq!(buffer.pop_front().unwrap(), Posting(DocId(0)));
+        assert_eq!(buffer.pop_front().unwrap(), Posting(DocId(1)));
+        assert_eq!(buffer.pop_front().unwrap(), Posting(DocId(1_000_000)));
+        assert_eq!(buffer.pop_front().unwrap(), Posting(DocId(DocId::none().0 - 1)));
+        assert_eq!(buffer.pop_front(), None);
+    }
+
+    #[test]
+    fn biased() {
+        let mut buffer = BiasedRingBuffer::<Posting>::new();
+        buffer.set_base(Posting(DocId(1000)));
+        for i in 0..10 {
+            buffer.push_back(Posting(DocId(1000 + i * 200)));
+        }
+        let block = VByteCompressor::force_compress(&mut buffer);
+        VByteCompressor::decompress(block, &mut buffer);
+        for i in 0..10 {
+            assert_eq!(buffer.pop_front().unwrap(), Posting(DocId(1000 + i * 200)));
+        }
+    }
+
+    #[test]
+    fn denser_than_naive() {
+        let mut naive = BiasedRingBuffer::<Posting>::new();
+        let mut vbyte = BiasedRingBuffer::<Posting>::new();
+        for i in 0..MAX_COUNT as u32 + 1 {
+            naive.push_back(Posting(DocId(i * 2)));
+            vbyte.push_back(Posting(DocId(i * 2)));
+        }
+        let naive_block = NaiveCompressor::compress(&mut naive).unwrap();
+        let vbyte_block = VByteCompressor::compress(&mut vbyte).unwrap();
+        naive.flush();
+        vbyte.flush();
+        NaiveCompressor::decompress(naive_block, &mut naive);
+        VByteCompressor::decompress(vbyte_block, &mut vbyte);
+        assert!(vbyte.count() > naive.count());
+        assert_eq!(vbyte.count(), MAX_COUNT);
+    }
+}
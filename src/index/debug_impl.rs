@@ -2,10 +2,11 @@ use std::hash::Hash;
 use std::mem;
 use std::fmt::{Formatter, Error, Debug, Display};
  
+use compressor::Compressor;
 use index::Index;
 use index::listing::Listing;
 
-impl<T: Hash + Eq> Debug for Index<T> {
+impl<T: Hash + Eq, C: Compressor> Debug for Index<T, C> {
 
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         if f.alternate() {
@@ -18,22 +19,22 @@ impl<T: Hash + Eq> Debug for Index<T> {
 }
 
 
-impl<T: Hash + Eq> Display for Index<T> {
+impl<T: Hash + Eq, C: Compressor> Display for Index<T, C> {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         display(self, f)
     }
 }
 
-fn display<T: Hash + Eq>(index: &Index<T>, f: &mut Formatter) -> Result<(), Error> {
+fn display<T: Hash + Eq, C: Compressor>(index: &Index<T, C>, f: &mut Formatter) -> Result<(), Error> {
     writeln!(f, "Index with {} Documents; Last DocId is {:?}", index.doc_count, index.last_doc_id)
 }
 
-fn debug<T: Hash + Eq>(index: &Index<T>, f: &mut Formatter) -> Result<(), Error> {
+fn debug<T: Hash + Eq, C: Compressor>(index: &Index<T, C>, f: &mut Formatter) -> Result<(), Error> {
     writeln!(f, "Index with {} Documents; Last DocId is {:?}", index.doc_count, index.last_doc_id)?;
     writeln!(f, "\tIt has {} listings!", index.listings.len())?;
-    writeln!(f, "\tThe listings heap size is {}!", index.listings.len() * mem::size_of::<Listing>()) 
+    writeln!(f, "\tThe listings heap size is {}!", index.listings.len() * mem::size_of::<Listing<C>>()) 
 }
 
-fn debug_verbose<T: Hash + Eq>(index: &Index<T>, f: &mut Formatter) -> Result<(), Error> {
+fn debug_verbose<T: Hash + Eq, C: Compressor>(index: &Index<T, C>, f: &mut Formatter) -> Result<(), Error> {
     writeln!(f, "Index with {} Documents; Last DocId is {:?}", index.doc_count, index.last_doc_id)
 }
@@ -1,36 +1,80 @@
-use utils::ring_buffer::BiasedRingBuffer;
+use std::mem;
+use std::thread;
+use std::marker::PhantomData;
+
+use utils::ring_buffer::{BiasedRingBuffer, SIZE};
 
 use compressor::{Compressor, NaiveCompressor};
 
-use page_manager::{Pages, PageId, Block, BlockIter, BlockId, RamPageCache, PageCache, BlockManager};
+use page_manager::{Pages, PageId, Block, BlockIter, BlockId, RamPageCache, PageCache, BlockManager,
+                   PAGESIZE};
 
 use index::posting::{Posting, DocId, PostingDecoder};
 
 pub type UsedCompressor = NaiveCompressor;
 
+/// Listings with at most this many postings are not shipped to a page on
+/// commit by default. They stay inline in the posting buffer instead.
+/// Small listings would share an unfull page with others anyway, so this
+/// saves the block they would occupy there and the page read to decode them.
+pub const INLINE_LIMIT: usize = 8;
+
+/// Postings of a single term, encoded into blocks by the compressor `C`.
+/// `N` is the capacity of the posting buffer. It has to hold the postings of
+/// a decompressed block and the 16 postings added between two compressions
 #[derive(Debug)]
-pub struct Listing {
+pub struct Listing<C = UsedCompressor, const N: usize = SIZE> {
     pages: Pages,
     current_page: Option<PageId>,
     block_biases: Vec<Posting>,
     block_counter: BlockId,
     block_start: Posting,
     block_end: Posting,
-    posting_buffer: BiasedRingBuffer<Posting>,
-    size: u32
+    posting_buffer: BiasedRingBuffer<Posting, N>,
+    size: u32,
+    inline: bool,
+    inline_limit: usize,
+    uncommitted: bool,
+    compressor: PhantomData<C>,
+}
+
+impl Default for Listing {
+    fn default() -> Self {
+        Listing::new()
+    }
 }
 
 impl Listing {
     pub fn new() -> Self {
+        Listing::with_inline_limit(INLINE_LIMIT)
+    }
+
+    /// Listing that is kept inline on commit as long as it has at most
+    /// `inline_limit` postings. 0 disables inlining.
+    /// Limits above the capacity of a block are capped by it.
+    pub fn with_inline_limit(inline_limit: usize) -> Self {
+        Listing::with_compressor(inline_limit)
+    }
+}
+
+impl<C: Compressor, const N: usize> Listing<C, N> {
+    /// Like `with_inline_limit`, but blocks are encoded by `C` instead of
+    /// `UsedCompressor`
+    pub fn with_compressor(inline_limit: usize) -> Self {
+        debug_assert!(N >= 32, "Posting buffer of {} postings is too small", N);
         Listing {
             pages: Pages::new(),
             current_page: None,
             block_biases: Vec::new(),
             block_counter: BlockId::first(),
-            posting_buffer: BiasedRingBuffer::new(),
+            posting_buffer: BiasedRingBuffer::default(),
             block_start: Posting(DocId(0)),
             block_end: Posting(DocId(0)),
             size: 0,
+            inline: false,
+            inline_limit,
+            uncommitted: false,
+            compressor: PhantomData,
         }
     }
 
@@ -38,12 +82,41 @@ impl Listing {
         self.size as usize
     }
 
+    /// False if postings were added since the last `commit`
+    pub fn is_committed(&self) -> bool {
+        !self.uncommitted
+    }
+
+    /// Accept that postings added since the last `commit` are lost when the
+    /// listing is dropped
+    pub fn discard_uncommitted(&mut self) {
+        self.uncommitted = false;
+    }
+
+    /// Number of blocks this listing has on flushed pages
+    pub fn stored_blocks(&self) -> usize {
+        let unfull_blocks = self.pages
+            .unfull()
+            .map_or(0, |unfull_page| (unfull_page.to().0 - unfull_page.from().0) as usize);
+        self.pages.0.len() * PAGESIZE + unfull_blocks
+    }
+
+    /// Approximate number of bytes this listing occupies in memory.
+    /// Blocks stored on pages are not included.
+    pub fn memory_usage(&self) -> usize {
+        mem::size_of::<Listing>() + self.pages.0.capacity() * mem::size_of::<PageId>() +
+        self.block_biases.capacity() * mem::size_of::<Posting>()
+    }
+
     pub fn add(&mut self, postings: &[Posting], page_cache: &mut RamPageCache) {
         // Check if we previously commited an unfull page
         // in that case it has to be unraveld
         if self.pages.unfull().is_some() {
             self.unravel_unfull(page_cache)
         }
+        // Inlined postings are still in the posting buffer. Just continue
+        self.inline = false;
+        self.uncommitted |= !postings.is_empty();
         for (i, posting) in postings.iter().enumerate() {
             // Don't allow duplicate postings for documents
             // If this test would not be here, term x could have multiple entries for one
@@ -65,26 +138,75 @@ impl Listing {
     }
 
     pub fn commit(&mut self, page_cache: &mut RamPageCache) {
+        self.uncommitted = false;
+        if self.block_biases.is_empty() && self.posting_buffer.count() <= self.inline_limit {
+            // Nothing was shipped yet and the listing is small: keep it inline
+            self.inline = true;
+            return;
+        }
         self.compress_and_ship(page_cache, true);
         // Currentpage has to be unfull. Or None
         if let Some(unfull_page) = self.current_page.take() {
             self.pages.add_unfull(page_cache.flush_unfull(unfull_page, self.block_counter));
             self.block_counter = BlockId::first();
         }
+        debug_assert!(self.is_consistent());
+    }
+
+    /// Decodes the whole listing and encodes it again from scratch on new
+    /// pages. This recomputes `block_biases`, `block_start` and `block_end`
+    /// and yields the same block layout as adding all postings at once.
+    ///
+    /// This is no migration between block or page geometries. Pages are read
+    /// with the current `BLOCKSIZE` and `PAGESIZE`, and blocks are stored
+    /// relative to their bias, so the current biases have to match the
+    /// stored pages.
+    pub fn rebuild(&mut self, page_cache: &mut RamPageCache) {
+        self.rebuild_retaining(page_cache, |_| true);
+    }
+
+    /// Like `rebuild`, but only postings for which `keep` returns true are
+    /// encoded again
+    pub fn rebuild_retaining<F>(&mut self, page_cache: &mut RamPageCache, keep: F)
+        where F: FnMut(&Posting) -> bool
+    {
+        self.commit(page_cache);
+        let postings = self.posting_decoder(page_cache).filter(keep).collect::<Vec<_>>();
+        // Old pages are not needed anymore
+        self.delete_pages(page_cache);
+        *self = Listing::with_compressor(self.inline_limit);
+        self.add(&postings, page_cache);
+        self.commit(page_cache);
+    }
+
+    /// Frees the committed pages of this listing in the page cache.
+    /// The listing must not be used afterwards, except for replacing it.
+    pub fn delete_pages(&mut self, page_cache: &mut RamPageCache) {
+        let pages = mem::take(&mut self.pages);
+        for page_id in pages.0 {
+            page_cache.delete_page(page_id);
+        }
+        if let Some(unfull_page) = pages.1 {
+            page_cache.delete_unfull(unfull_page.page_id());
+        }
     }
 
     /// Construct a posting decoder for this listing
-    pub fn posting_decoder<'a>(&'a self, cache: &'a RamPageCache) -> PostingDecoder<'a> {
+    pub fn posting_decoder<'a>(&'a self, cache: &'a RamPageCache) -> PostingDecoder<'a, N> {
         let block_iter = BlockIter::new(cache, self.pages.clone());
+        if self.inline {
+            return PostingDecoder::inline(block_iter, self.posting_buffer.clone(), self.size);
+        }
         PostingDecoder::new(block_iter, &self.block_biases, self.size)
+            .with_decompressor(C::decompress)
     }
 
     fn compress_and_ship(&mut self, page_cache: &mut RamPageCache, force: bool) {
-        while let Some(block) = UsedCompressor::compress(&mut self.posting_buffer) {
+        while let Some(block) = C::compress(&mut self.posting_buffer) {
             self.ship(page_cache, block);
         }
         if force && self.posting_buffer.count() > 0 {
-            let block = UsedCompressor::force_compress(&mut self.posting_buffer);
+            let block = C::force_compress(&mut self.posting_buffer);
             self.ship(page_cache, block);
         }
     }
@@ -102,26 +224,44 @@ impl Listing {
         assert!(self.current_page.is_none());
         if let Some(unfull_page) = self.pages.take_unfull() {
             // Build the postings
+            // Get the block count of the unfull page
+            let block_count = (unfull_page.to().0 - unfull_page.from().0) as usize;
+            let first_bias = self.block_biases.len() - block_count;
             let postings = {
-                // Get the block count of the unfull page
-                let block_count = unfull_page.to().0 - unfull_page.from().0;
                 // build the block iter
                 let block_iter = BlockIter::new(page_cache, Pages(vec![], Some(unfull_page)));
                 // Set postings_buffer old base
-                self.posting_buffer.set_base(self.block_biases[self.block_biases.len() - block_count as usize]);
+                self.posting_buffer.set_base(self.block_biases[first_bias]);
                 // Decode the postings through a decoder
-                PostingDecoder::new(block_iter,
-                                    &self.block_biases[self.block_biases.len() -
-                                     block_count as usize..], self.size)
+                PostingDecoder::new(block_iter, &self.block_biases[first_bias..], self.size)
+                        .with_decompressor(C::decompress::<N>)
                         .collect::<Vec<_>>()
             };
+            // The unraveled blocks will be shipped again. Forget their biases
+            // and rewind to the state before they were shipped.
+            self.block_start = self.block_biases[first_bias];
+            self.block_biases.truncate(first_bias);
+            self.size -= postings.len() as u32;
             self.block_counter = BlockId::first();
+            debug_assert!(self.is_consistent());
             self.add(&postings, page_cache);
             // Previous unfull page can now be deleted!
             page_cache.delete_unfull(unfull_page.page_id());
         }
     }
 
+    /// Checks that there is exactly one bias for every shipped block.
+    /// Blocks are shipped to full pages, the unfull page or the page
+    /// currently under construction.
+    pub fn is_consistent(&self) -> bool {
+        let current_blocks = if self.current_page.is_some() {
+            self.block_counter.0 as usize
+        } else {
+            0
+        };
+        self.block_biases.len() == self.stored_blocks() + current_blocks
+    }
+
     /// This method does three things:
     /// 1. It pushes a block to an existing page in ram or demands for one to
     /// be created
@@ -157,6 +297,18 @@ impl Listing {
         }
 
         self.posting_buffer.set_base(self.block_start);
+        debug_assert!(self.is_consistent());
+    }
+}
+
+impl<C, const N: usize> Drop for Listing<C, N> {
+    /// `commit` needs the page cache, which a listing does not hold. So
+    /// uncommitted postings cannot be saved here. Owners have to commit or
+    /// explicitly discard them before.
+    fn drop(&mut self) {
+        debug_assert!(!self.uncommitted || thread::panicking(),
+                      "Listing with {} postings dropped without commit",
+                      self.size);
     }
 }
 
@@ -164,12 +316,14 @@ impl Listing {
 #[cfg(test)]
 mod tests {
 
-    use super::Listing;
+    use super::{Listing, INLINE_LIMIT};
 
     use test_utils::create_test_dir;
 
+    use compressor::{NaiveCompressor, VByteCompressor};
     use index::posting::{Posting, DocId};
     use page_manager::{FsPageManager, RamPageCache};
+    use utils::seeking_iterator::SeekingIterator;
 
 
     fn new_cache(name: &str) -> RamPageCache {
@@ -185,12 +339,13 @@ mod tests {
         listing.add(&[Posting(DocId(0))], &mut cache);
         assert_eq!(listing.pages.len(), 0);
         assert_eq!(listing.posting_buffer.count(), 1);
+        listing.discard_uncommitted();
     }
 
     #[test]
     fn commit() {
         let mut cache = new_cache("commit");
-        let mut listing = Listing::new();
+        let mut listing = Listing::with_inline_limit(0);
         listing.add(&[Posting(DocId(0))], &mut cache);
         assert_eq!(listing.pages.len(), 0);
         assert_eq!(listing.posting_buffer.count(), 1);
@@ -251,7 +406,7 @@ mod tests {
     #[test]
     fn biases() {
         let mut cache = new_cache("biases");
-        let mut listing = Listing::new();
+        let mut listing = Listing::with_inline_limit(0);
 
         listing.add(&[Posting(DocId(1))], &mut cache);
         assert_eq!(listing.block_start, Posting(DocId(0)));
@@ -263,7 +418,146 @@ mod tests {
         assert_eq!(listing.block_end, Posting(DocId(10)));
         listing.commit(&mut cache);
         assert_eq!(listing.block_start, Posting(DocId(10)));
-        assert_eq!(listing.block_biases,
-                   vec![Posting(DocId(0)), Posting(DocId(1))]);
+        // The unfull block was unraveled and shipped again. Its bias is reused
+        assert_eq!(listing.block_biases, vec![Posting(DocId(0))]);
+        assert!(listing.is_consistent());
+        assert_eq!(listing.posting_decoder(&cache).collect::<Vec<_>>(),
+                   vec![Posting(DocId(1)), Posting(DocId(10))]);
+    }
+
+    #[test]
+    fn inline() {
+        let mut cache = new_cache("inline");
+        let mut listing = Listing::new();
+        listing.add(&[Posting(DocId(3))], &mut cache);
+        listing.commit(&mut cache);
+        assert!(listing.inline);
+        assert_eq!(listing.pages.len(), 0);
+        assert_eq!(listing.posting_decoder(&cache).collect::<Vec<_>>(),
+                   vec![Posting(DocId(3))]);
+        assert_eq!(listing.posting_decoder(&cache).next_seek(&Posting(DocId(2))),
+                   Some(Posting(DocId(3))));
+        // Growing beyond the limit ships the listing to a page
+        let postings = (4..4 + INLINE_LIMIT as u32).map(|i| Posting(DocId(i))).collect::<Vec<_>>();
+        listing.add(&postings, &mut cache);
+        listing.commit(&mut cache);
+        assert!(!listing.inline);
+        assert_eq!(listing.pages.len(), 1);
+        assert_eq!(listing.posting_decoder(&cache).collect::<Vec<_>>(),
+                   (3..4 + INLINE_LIMIT as u32).map(|i| Posting(DocId(i))).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn add_after_commit() {
+        let mut cache = new_cache("add_after_commit");
+        let mut listing = Listing::new();
+        for i in 0..10000 {
+            listing.add(&[Posting(DocId(i * 3))], &mut cache);
+            if i % 7 == 0 {
+                listing.commit(&mut cache);
+            }
+            assert!(listing.is_consistent());
+        }
+        listing.commit(&mut cache);
+        assert!(listing.is_consistent());
+        assert_eq!(listing.len(), 10000);
+        assert!(listing.posting_decoder(&cache).collect::<Vec<_>>() ==
+                (0..10000).map(|i| Posting(DocId(i * 3))).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rebuild() {
+        let mut cache = new_cache("rebuild");
+        let postings = (0..10000).map(|i| Posting(DocId(i * 3))).collect::<Vec<_>>();
+        let mut listing = Listing::new();
+        for posting in &postings {
+            listing.add(&[*posting], &mut cache);
+        }
+        listing.commit(&mut cache);
+        // Adding everything at once yields the canonical block layout
+        let mut reference = Listing::new();
+        reference.add(&postings, &mut cache);
+        reference.commit(&mut cache);
+
+        listing.rebuild(&mut cache);
+        assert_eq!(listing.len(), 10000);
+        assert!(listing.block_biases == reference.block_biases);
+        assert!(listing.posting_decoder(&cache).collect::<Vec<_>>() == postings);
+        let mut decoder = listing.posting_decoder(&cache);
+        assert_eq!(decoder.next_seek(&Posting(DocId(15001))),
+                   Some(Posting(DocId(15003))));
+        assert_eq!(decoder.next_seek(&Posting(DocId(29997))),
+                   Some(Posting(DocId(29997))));
+        assert_eq!(decoder.next(), None);
+    }
+
+    #[test]
+    fn drop_uncommitted() {
+        let mut cache = new_cache("drop_uncommitted");
+        let mut listing = Listing::new();
+        assert!(listing.is_committed());
+        listing.add(&[Posting(DocId(0))], &mut cache);
+        assert!(!listing.is_committed());
+        listing.commit(&mut cache);
+        assert!(listing.is_committed());
+        listing.add(&[], &mut cache);
+        assert!(listing.is_committed());
+        listing.add(&[Posting(DocId(1))], &mut cache);
+        listing.discard_uncommitted();
+        // Dropping it now loses the posting, but was asked for
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dropped without commit")]
+    fn drop_uncommitted_asserts() {
+        let mut cache = new_cache("drop_uncommitted_asserts");
+        let mut listing = Listing::new();
+        listing.add(&[Posting(DocId(0))], &mut cache);
+    }
+
+    #[test]
+    fn vbyte_compressor() {
+        let mut cache = new_cache("vbyte_compressor");
+        let mut listing = Listing::<VByteCompressor>::with_compressor(0);
+        // Dense postings added in bulk and one by one, then sparse ones
+        let dense = (0..5000).map(|i| Posting(DocId(i))).collect::<Vec<_>>();
+        listing.add(&dense, &mut cache);
+        for i in 5000..6000 {
+            listing.add(&[Posting(DocId(i))], &mut cache);
+        }
+        listing.commit(&mut cache);
+        let sparse = (0..3000).map(|i| Posting(DocId(6000 + i * 1000))).collect::<Vec<_>>();
+        listing.add(&sparse, &mut cache);
+        listing.commit(&mut cache);
+        let expected = (0..6000).map(|i| Posting(DocId(i))).chain(sparse).collect::<Vec<_>>();
+        assert_eq!(listing.len(), expected.len());
+        assert_eq!(listing.posting_decoder(&cache).collect::<Vec<_>>(), expected);
+        assert_eq!(listing.posting_decoder(&cache).next_seek(&Posting(DocId(2_000_500))),
+                   Some(Posting(DocId(2_001_000))));
+        listing.rebuild(&mut cache);
+        assert_eq!(listing.posting_decoder(&cache).collect::<Vec<_>>(), expected);
+    }
+    #[test]
+    fn buffer_capacity() {
+        let mut cache = new_cache("buffer_capacity");
+        let postings = (0..5000).map(|i| Posting(DocId(i * 3))).collect::<Vec<_>>();
+        let mut small = Listing::<NaiveCompressor, 32>::with_compressor(0);
+        let mut vbyte = Listing::<VByteCompressor>::with_compressor(0);
+        let mut large = Listing::<VByteCompressor, 128>::with_compressor(0);
+        for chunk in postings.chunks(700) {
+            small.add(chunk, &mut cache);
+            vbyte.add(chunk, &mut cache);
+            large.add(chunk, &mut cache);
+            small.commit(&mut cache);
+            vbyte.commit(&mut cache);
+            large.commit(&mut cache);
+        }
+        assert_eq!(small.posting_decoder(&cache).collect::<Vec<_>>(), postings);
+        assert_eq!(large.posting_decoder(&cache).collect::<Vec<_>>(), postings);
+        assert_eq!(large.posting_decoder(&cache).next_seek(&Posting(DocId(10_000))),
+                   Some(Posting(DocId(10_002))));
+        // Blocks are no longer capped by the default capacity
+        assert!(large.stored_blocks() < vbyte.stored_blocks());
     }
 }
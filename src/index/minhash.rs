@@ -0,0 +1,89 @@
+//! MinHash signatures for near-duplicate detection.
+//!
+//! A document is broken up into shingles (overlapping windows of consecutive
+//! terms). Terms are hashed by value, not by TermId, so signatures stay valid
+//! when documents move to an index with a different vocabulary. For each of `SIGNATURE_LEN` hash functions the signature keeps
+//! the smallest hash over all shingles. The fraction of equal slots between two
+//! signatures estimates the jaccard similarity of their shingle sets.
+use std::cmp;
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+
+const SIGNATURE_LEN: usize = 64;
+const SHINGLE_SIZE: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Signature([u64; SIGNATURE_LEN]);
+
+impl Signature {
+    /// Computes the signature of a document given its terms in document
+    /// order. Documents shorter than a shingle form a single shingle.
+    pub fn from_terms<T: Hash>(terms: &[T]) -> Self {
+        let mut mins = [u64::MAX; SIGNATURE_LEN];
+        let size = terms.len().clamp(1, SHINGLE_SIZE);
+        for shingle in terms.windows(size) {
+            let mut hasher = DefaultHasher::new();
+            for term in shingle {
+                term.hash(&mut hasher);
+            }
+            let base = hasher.finish();
+            for (i, min) in mins.iter_mut().enumerate() {
+                *min = cmp::min(*min, permute(base, i as u64));
+            }
+        }
+        Signature(mins)
+    }
+
+    /// Estimated jaccard similarity between 0 and 1
+    pub fn similarity(&self, other: &Signature) -> f32 {
+        let equal = self.0.iter().zip(other.0.iter()).filter(|&(a, b)| a == b).count();
+        equal as f32 / SIGNATURE_LEN as f32
+    }
+}
+
+/// Hash of a single term. Equal terms hash equally in every index
+pub fn term_hash<T: Hash>(term: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    term.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Derives the i-th hash function from a single base hash (splitmix64
+/// finalizer over a per-function offset)
+#[inline]
+fn permute(base: u64, i: u64) -> u64 {
+    let mut z = base.wrapping_add((i + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Signature;
+
+    fn terms(from: u64, to: u64) -> Vec<u64> {
+        (from..to).collect()
+    }
+
+    #[test]
+    fn identical() {
+        let a = Signature::from_terms(&terms(0, 100));
+        let b = Signature::from_terms(&terms(0, 100));
+        assert_eq!(a.similarity(&b), 1.0);
+    }
+
+    #[test]
+    fn disjoint() {
+        let a = Signature::from_terms(&terms(0, 100));
+        let b = Signature::from_terms(&terms(100, 200));
+        assert!(a.similarity(&b) < 0.1);
+    }
+
+    #[test]
+    fn single_term() {
+        let a = Signature::from_terms(&terms(0, 1));
+        let b = Signature::from_terms(&terms(0, 1));
+        assert_eq!(a.similarity(&b), 1.0);
+    }
+}
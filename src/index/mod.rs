@@ -1,24 +1,40 @@
+use std::mem;
 use std::hash::Hash;
-use std::collections::BTreeMap;
+use std::ops::RangeBounds;
+use std::sync::{OnceLock, RwLock};
+use std::thread;
+use std::collections::{BTreeMap, BTreeSet};
 
-use page_manager::RamPageCache;
-use index::listing::Listing;
-use index::posting::{DocId, Posting, PostingIterator};
+use page_manager::{RamPageCache, BLOCKSIZE};
+use compressor::Compressor;
+use index::listing::{Listing, UsedCompressor, INLINE_LIMIT};
+use index::posting::{DocId, Posting, PostingDecoder, PostingIterator};
 use index::vocabulary::{Vocabulary, TermId, SharedVocabulary, TermIterator};
+use index::minhash::{Signature, term_hash};
+use utils::seeking_iterator::SeekingIterator;
 
 pub mod vocabulary;
 pub mod posting;
 mod listing;
+mod minhash;
 mod debug_impl;
 
 /// Central struct of perlin
-/// Stores and manages an index with its listings and vocabulary
-pub struct Index<TTerm: Hash + Eq> {
+/// Stores and manages an index with its listings and vocabulary.
+/// The listings are encoded by the compressor `C`
+pub struct Index<TTerm: Hash + Eq, C: Compressor = UsedCompressor> {
     page_manager: RamPageCache,
-    listings: BTreeMap<TermId, Listing>,
+    listings: BTreeMap<TermId, Listing<C>>,
     vocabulary: SharedVocabulary<TTerm>,
     last_doc_id: DocId,
     doc_count: usize,
+    signatures: Option<BTreeMap<DocId, Signature>>,
+    deleted: BTreeSet<DocId>,
+    inline_limit: usize,
+    idf_cache: RwLock<BTreeMap<TermId, f32>>,
+    doc_lengths: BTreeMap<DocId, u32>,
+    compaction_cursor: Option<TermId>,
+    term_lookup: OnceLock<BTreeMap<TermId, TTerm>>,
 }
 
 /// The inverse document frequency defined by
@@ -32,34 +48,87 @@ impl InverseDocumentFrequency {
     }
 }
 
+/// A snapshot of the size of an index. E.g. for monitoring
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexStatistics {
+    /// Number of indexed documents
+    pub document_count: usize,
+    /// Number of indexed documents that were not deleted
+    pub live_document_count: usize,
+    /// Number of terms with a listing in this index
+    pub term_count: usize,
+    /// Number of terms in the vocabulary, including pruned ones
+    pub vocabulary_size: usize,
+    /// Average number of terms in the documents that were not deleted
+    pub average_document_length: f32,
+    /// Number of postings over all listings
+    pub posting_count: usize,
+    /// Bytes occupied by the committed blocks of all listings
+    pub storage_bytes: usize,
+}
 
 impl<TTerm> Index<TTerm>
     where TTerm: Hash + Ord
 {
     pub fn new(page_manager: RamPageCache, vocabulary: SharedVocabulary<TTerm>) -> Self {
+        Index::with_compressor(page_manager, vocabulary)
+    }
+}
+
+impl<TTerm, C> Index<TTerm, C>
+    where TTerm: Hash + Ord,
+          C: Compressor
+{
+    /// Creates an index whose listings are encoded by `C` instead of
+    /// `UsedCompressor`
+    pub fn with_compressor(page_manager: RamPageCache,
+                           vocabulary: SharedVocabulary<TTerm>)
+                           -> Self {
         Index {
             page_manager: page_manager,
             listings: BTreeMap::new(),
             vocabulary: vocabulary,
             last_doc_id: DocId::none(),
             doc_count: 0,
+            signatures: None,
+            deleted: BTreeSet::new(),
+            inline_limit: INLINE_LIMIT,
+            idf_cache: RwLock::new(BTreeMap::new()),
+            doc_lengths: BTreeMap::new(),
+            compaction_cursor: None,
+            term_lookup: OnceLock::new(),
         }
 
     }
 
+    /// Listings with at most `limit` postings are kept in memory on commit
+    /// instead of allocating a page for them. 0 disables this.
+    /// Only affects listings of terms that are new to this index.
+    pub fn set_inline_limit(&mut self, limit: usize) {
+        self.inline_limit = limit;
+    }
+
     pub fn index_term(&mut self, doc_id: DocId, term: TTerm) {
         // Assert one critical assumption about the doc_id:
         // It must not be smaller than any previous doc_ids!
         // If it is, fail hard before something bad happens!
         assert!(doc_id >= self.last_doc_id || self.last_doc_id == DocId::none());
         self.last_doc_id = doc_id;
+        self.invalidate_idf();
+        let length = self.doc_lengths.entry(doc_id).or_insert(0);
+        if *length == 0 {
+            // First term of this document
+            self.doc_count += 1;
+        }
+        *length += 1;
         // Resolve term
         let term_id = self.vocabulary.get_or_add(term);
+        self.invalidate_term_lookup();
         if let Some(listing) = self.listings.get_mut(&term_id) {
             listing.add(&[Posting(doc_id)], &mut self.page_manager);
             return;
         }
-        let mut new_listing = Listing::new();
+        let mut new_listing = Listing::with_compressor(self.inline_limit);
         new_listing.add(&[Posting(doc_id)], &mut self.page_manager);
         self.listings.insert(term_id, new_listing);
     }
@@ -87,11 +156,25 @@ impl<TTerm> Index<TTerm>
             self.last_doc_id
         };
         self.doc_count += 1;
+        self.invalidate_idf();
         let mut buff = Vec::new();
+        // Signatures hash the terms themselves, which are moved into the
+        // vocabulary below
+        let mut term_hashes = Vec::new();
         for term in document {
+            if self.signatures.is_some() {
+                term_hashes.push(term_hash(&term));
+            }
             let term_id = self.vocabulary.get_or_add(term);
             buff.push(term_id);
         }
+        self.invalidate_term_lookup();
+        self.doc_lengths.insert(doc_id, buff.len() as u32);
+        if let Some(ref mut signatures) = self.signatures {
+            if !term_hashes.is_empty() {
+                signatures.insert(doc_id, Signature::from_terms(&term_hashes));
+            }
+        }
         buff.sort();
         buff.dedup();
         for term_id in buff {
@@ -100,13 +183,40 @@ impl<TTerm> Index<TTerm>
                 listing.add(&[Posting(doc_id)], &mut self.page_manager);
                 continue;
             };
-            let mut new_listing = Listing::new();
+            let mut new_listing = Listing::with_compressor(self.inline_limit);
             new_listing.add(&[Posting(doc_id)], &mut self.page_manager);
             self.listings.insert(term_id, new_listing);
         }
         doc_id
     }
 
+    /// Index several documents with assigned doc ids.
+    /// After every `every` documents and after the last one, `progress` is
+    /// called with the number of documents indexed by this call so far and
+    /// the number of distinct terms in this index.
+    pub fn index_documents<TDocs, TIter, F>(&mut self,
+                                           documents: TDocs,
+                                           every: usize,
+                                           mut progress: F)
+                                           -> Vec<DocId>
+        where TDocs: Iterator<Item = TIter>,
+              TIter: Iterator<Item = TTerm>,
+              F: FnMut(usize, usize)
+    {
+        assert!(every > 0);
+        let mut doc_ids = Vec::new();
+        for document in documents {
+            doc_ids.push(self.index_document(document, None));
+            if doc_ids.len() % every == 0 {
+                progress(doc_ids.len(), self.listings.len());
+            }
+        }
+        if doc_ids.len() % every != 0 {
+            progress(doc_ids.len(), self.listings.len());
+        }
+        doc_ids
+    }
+
     /// Commits listings to page manager and makes them retrievable
     /// If this method is not called before querying you will not be happy!
     // TODO: Find a way if we can make this a compile-time error or warning
@@ -120,11 +230,160 @@ impl<TTerm> Index<TTerm>
         }
     }
 
+    /// Commits like `commit` and then syncs the page file to disk.
+    /// This blocks until the OS reports the data as written.
+    /// Only the page file is synced. Inline listings, the vocabulary and the
+    /// listing metadata live in memory, so this alone does not make the index
+    /// recoverable after a crash.
+    pub fn commit_durable(&mut self) {
+        self.commit();
+        self.page_manager.sync();
+    }
+
+    /// Appends all documents of `other` to this index.
+    /// The DocIds of `other` are shifted to start right after the last DocId
+    /// of this index, so the result equals indexing the documents of both
+    /// indices into one, one after the other.
+    ///
+    /// Both indices are committed, so pending postings of `other` are merged
+    /// as well.
+    pub fn merge(&mut self, mut other: Index<TTerm, C>) {
+        let offset = if self.last_doc_id == DocId::none() {
+            0
+        } else {
+            self.last_doc_id.0 + 1
+        };
+        let shift = |doc_id: DocId| DocId(doc_id.0 + offset);
+        other.commit();
+        // Index implements Drop, so its fields can't be moved out directly
+        let vocabulary = mem::replace(&mut other.vocabulary, SharedVocabulary::new());
+        let deleted = mem::take(&mut other.deleted);
+        let doc_lengths = mem::take(&mut other.doc_lengths);
+        let signatures = other.signatures.take();
+        for (term, other_term_id) in vocabulary {
+            let postings = match other.listings.get(&other_term_id) {
+                Some(listing) => {
+                    listing.posting_decoder(&other.page_manager)
+                        .map(|posting| Posting(shift(posting.doc_id())))
+                        .collect::<Vec<_>>()
+                }
+                None => continue,
+            };
+            let term_id = self.vocabulary.get_or_add(term);
+            let inline_limit = self.inline_limit;
+            self.listings
+                .entry(term_id)
+                .or_insert_with(|| Listing::with_compressor(inline_limit))
+                .add(&postings, &mut self.page_manager);
+        }
+        self.invalidate_term_lookup();
+        if other.last_doc_id != DocId::none() {
+            self.last_doc_id = shift(other.last_doc_id);
+        }
+        self.doc_count += other.doc_count;
+        if !deleted.is_empty() {
+            // Listings below the cursor were compacted without these
+            self.compaction_cursor = None;
+        }
+        self.deleted.extend(deleted.into_iter().map(&shift));
+        self.doc_lengths.extend(doc_lengths.into_iter().map(|(doc_id, length)| (shift(doc_id), length)));
+        // Signatures do not depend on TermIds, so they stay valid
+        if let (Some(signatures), Some(other_signatures)) = (self.signatures.as_mut(), signatures) {
+            signatures.extend(other_signatures.into_iter().map(|(doc_id, signature)| (shift(doc_id), signature)));
+        }
+        self.invalidate_idf();
+        self.commit();
+    }
+
+    /// Drops the listings of all terms occurring in fewer than `min_df`
+    /// documents and frees their pages. Returns how many were dropped.
+    ///
+    /// Queries for pruned terms return no results afterwards. The terms stay
+    /// in the vocabulary, so their TermIds are never reassigned.
+    /// Pending postings are committed first.
+    pub fn prune_rare_terms(&mut self, min_df: usize) -> usize {
+        self.commit();
+        let rare = self.listings
+            .iter()
+            .filter(|&(_, listing)| listing.len() < min_df)
+            .map(|(term_id, _)| *term_id)
+            .collect::<Vec<_>>();
+        for term_id in &rare {
+            let mut listing = self.listings.remove(term_id).unwrap();
+            listing.delete_pages(&mut self.page_manager);
+        }
+        self.invalidate_idf();
+        rare.len()
+    }
+
+    /// Removes the postings of deleted documents from the next listing.
+    /// Returns whether more listings remain, so compaction can be spread
+    /// over several calls. Deleting a document or merging an index with
+    /// deleted documents restarts compaction.
+    ///
+    /// Once all listings are compacted, deleted documents are forgotten and
+    /// no longer counted by `document_count`.
+    pub fn compact_step(&mut self) -> bool {
+        if self.deleted.is_empty() {
+            return false;
+        }
+        let cursor = self.compaction_cursor.unwrap_or(TermId(0));
+        let term_id = match self.listings.range(cursor..).next() {
+            Some((term_id, _)) => *term_id,
+            None => {
+                // Done. No listing contains deleted documents anymore
+                self.doc_count -= self.deleted.len();
+                for doc_id in &self.deleted {
+                    self.doc_lengths.remove(doc_id);
+                }
+                self.deleted.clear();
+                self.compaction_cursor = None;
+                self.invalidate_idf();
+                return false;
+            }
+        };
+        {
+            let deleted = &self.deleted;
+            let listing = self.listings.get_mut(&term_id).unwrap();
+            listing.rebuild_retaining(&mut self.page_manager,
+                                      |posting| !deleted.contains(&posting.doc_id()));
+        }
+        if self.listings[&term_id].len() == 0 {
+            let mut listing = self.listings.remove(&term_id).unwrap();
+            listing.delete_pages(&mut self.page_manager);
+        }
+        // The document frequency of this term changed
+        self.idf_cache.get_mut().unwrap().remove(&term_id);
+        self.compaction_cursor = Some(TermId(term_id.0 + 1));
+        true
+    }
+
+    /// Removes the postings of all deleted documents at once
+    pub fn compact(&mut self) {
+        while self.compact_step() {}
+    }
+
+    /// Decodes and reencodes all listings from scratch, e.g. to defragment
+    /// listings that were built by many small additions.
+    /// Migrating between block or page geometries is not supported.
+    pub fn rebuild(&mut self) {
+        for listing in self.listings.values_mut() {
+            listing.rebuild(&mut self.page_manager);
+        }
+    }
+
     /// Get the TermId for a certain Term
     pub fn get_term_id(&self, atom: &TTerm) -> Option<TermId> {
         self.vocabulary.get(atom)
     }
 
+    /// True if this index has a listing for the term.
+    /// Terms only known to a shared vocabulary or pruned from this index
+    /// don't count.
+    pub fn contains_term(&self, atom: &TTerm) -> bool {
+        self.vocabulary.get(atom).is_some_and(|term_id| self.listings.contains_key(&term_id))
+    }
+
     /// Get all DocumentIds and its inverse document frequency of a single term
     pub fn query_atom(&self, atom: &TTerm) -> (InverseDocumentFrequency, PostingIterator) {
         if let Some(term_id) = self.vocabulary.get(atom) {
@@ -134,7 +393,7 @@ impl<TTerm> Index<TTerm>
                 // Might not be the case for a shared vocabulary!
                 let decoder = listing.posting_decoder(&self.page_manager);
                 let idf = InverseDocumentFrequency::from(self.doc_count, decoder.len());
-                return (idf, PostingIterator::Decoder(decoder));
+                return (idf, self.posting_iterator(decoder));
             }
         }
         // Term not found, return an empty iterator!
@@ -146,12 +405,152 @@ impl<TTerm> Index<TTerm>
         if let Some(listing) = self.listings.get(term_id) {
             let decoder = listing.posting_decoder(&self.page_manager);
             let idf = InverseDocumentFrequency::from(self.doc_count, decoder.len());
-            return (idf, PostingIterator::Decoder(decoder));
+            return (idf, self.posting_iterator(decoder));
         }
         // Unkown term id. Return an empty Iterator
         (InverseDocumentFrequency(0.0), PostingIterator::Empty)
     }
 
+    // Deleted documents must never surface in query results
+    fn posting_iterator<'a>(&'a self, decoder: PostingDecoder<'a>) -> PostingIterator<'a> {
+        if self.deleted.is_empty() {
+            PostingIterator::Decoder(decoder)
+        } else {
+            PostingIterator::Filtered(decoder, &self.deleted)
+        }
+    }
+
+    /// Marks a document as deleted. It will not be part of any query result
+    /// from now on. Its postings stay in the listings though.
+    ///
+    /// Deleting a document twice or a DocId that was never indexed is a
+    /// no-op.
+    pub fn delete_document(&mut self, doc_id: DocId) {
+        if !self.doc_lengths.contains_key(&doc_id) {
+            return;
+        }
+        if self.deleted.insert(doc_id) {
+            self.invalidate_idf();
+            // Already compacted listings might contain this document
+            self.compaction_cursor = None;
+            if let Some(ref mut signatures) = self.signatures {
+                signatures.remove(&doc_id);
+            }
+        }
+    }
+
+    /// Was this document deleted?
+    pub fn is_deleted(&self, doc_id: DocId) -> bool {
+        self.deleted.contains(&doc_id)
+    }
+
+    /// Number of documents that were indexed and not deleted.
+    /// In contrast to `statistics().document_count` which also counts
+    /// deleted documents until they are compacted away.
+    pub fn live_document_count(&self) -> usize {
+        self.doc_count - self.deleted.len()
+    }
+
+    /// Get the postings of a term up to and including `max_doc_id`.
+    /// Decoding stops at the first posting beyond that, so the tail of a long
+    /// listing is never touched.
+    pub fn postings_until(&self, atom: &TTerm, max_doc_id: DocId) -> Vec<Posting> {
+        self.query_atom(atom)
+            .1
+            .take_while(|posting| posting.doc_id() <= max_doc_id)
+            .collect()
+    }
+
+    /// Iterate over the gaps between consecutive DocIds of a term's listing.
+    /// Helpful to analyze the gap distribution when choosing a compressor.
+    pub fn doc_id_deltas<'a>(&'a self, atom: &TTerm) -> impl Iterator<Item = u32> + 'a {
+        let mut last: Option<DocId> = None;
+        self.query_atom(atom).1.filter_map(move |posting| {
+            let delta = last.map(|last| posting.doc_id().0 - last.0);
+            last = Some(posting.doc_id());
+            delta
+        })
+    }
+
+    /// Restrict a sorted slice of DocIds to those that also contain `atom`.
+    /// The term's listing is seeked through, so blocks that cannot contain
+    /// any of the DocIds are skipped.
+    pub fn refine(&self, doc_ids: &[DocId], atom: &TTerm) -> Vec<DocId> {
+        let listing = match self.vocabulary.get(atom).and_then(|term_id| self.listings.get(&term_id)) {
+            Some(listing) => listing,
+            None => return Vec::new(),
+        };
+        let mut decoder = listing.posting_decoder(&self.page_manager);
+        let mut current: Option<Posting> = None;
+        let mut result = Vec::new();
+        for doc_id in doc_ids {
+            // Only seek if the last found posting is behind this doc_id
+            if current.is_none_or(|posting| posting.doc_id() < *doc_id) {
+                current = decoder.next_seek(&Posting(*doc_id));
+            }
+            match current {
+                Some(posting) if posting.doc_id() == *doc_id && !self.is_deleted(*doc_id) => {
+                    result.push(*doc_id)
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        result
+    }
+
+    /// Number of documents indexed so far.
+    /// Includes deleted documents until `compact` removes them
+    pub fn document_count(&self) -> usize {
+        self.doc_count
+    }
+
+    /// Number of terms a document consisted of, including repeated terms.
+    /// `None` for unknown or deleted documents
+    pub fn document_length(&self, doc_id: DocId) -> Option<u32> {
+        if self.is_deleted(doc_id) {
+            return None;
+        }
+        self.doc_lengths.get(&doc_id).cloned()
+    }
+
+    /// Number of terms with a listing in this index
+    pub fn term_count(&self) -> usize {
+        self.listings.len()
+    }
+
+    /// In how many documents does this term occur?
+    /// 0 for unknown terms
+    pub fn document_frequency(&self, atom: &TTerm) -> usize {
+        self.vocabulary.get(atom).map_or(0, |term_id| self.term_df(&term_id))
+    }
+
+    /// Inverse document frequency of a term. `None` for unknown terms.
+    /// Computed values are cached until the next document is indexed or
+    /// deleted.
+    pub fn term_idf(&self, atom: &TTerm) -> Option<f32> {
+        let term_id = self.vocabulary.get(atom)?;
+        if let Some(idf) = self.idf_cache.read().unwrap().get(&term_id) {
+            return Some(*idf);
+        }
+        let listing = self.listings.get(&term_id)?;
+        let idf = InverseDocumentFrequency::from(self.doc_count, listing.len()).0;
+        self.idf_cache.write().unwrap().insert(term_id, idf);
+        Some(idf)
+    }
+
+    fn invalidate_idf(&mut self) {
+        self.idf_cache.get_mut().unwrap().clear();
+    }
+
+    /// Drops the reverse lookup of `term_of_id` if the vocabulary grew since
+    /// it was built. Terms are never removed, so growing is the only change.
+    fn invalidate_term_lookup(&mut self) {
+        if self.term_lookup.get().is_some_and(|lookup| lookup.len() != self.vocabulary.len()) {
+            self.term_lookup.take();
+        }
+    }
+
     /// In how many documents does this term occur?
     pub fn term_df(&self, term_id: &TermId) -> usize {
         if let Some(listing) = self.listings.get(term_id) {
@@ -160,27 +559,255 @@ impl<TTerm> Index<TTerm>
         // Unkown term. DF must be 0
         0
     }
+
+    /// Bins the terms of this index by their document frequency.
+    /// `buckets` are ascending, inclusive upper bounds. The returned vector
+    /// has one additional bucket for all terms exceeding the last bound.
+    pub fn listing_length_histogram(&self, buckets: &[usize]) -> Vec<usize> {
+        let mut histogram = vec![0; buckets.len() + 1];
+        for listing in self.listings.values() {
+            let bucket = match buckets.binary_search(&listing.len()) {
+                Ok(index) | Err(index) => index,
+            };
+            histogram[bucket] += 1;
+        }
+        histogram
+    }
+
+    /// Get a snapshot of the index' size
+    pub fn statistics(&self) -> IndexStatistics {
+        let (live_length, live_documents) = self.doc_lengths
+            .iter()
+            .filter(|&(doc_id, _)| !self.deleted.contains(doc_id))
+            .fold((0u64, 0usize), |(sum, count), (_, length)| (sum + *length as u64, count + 1));
+        IndexStatistics {
+            document_count: self.doc_count,
+            live_document_count: self.live_document_count(),
+            term_count: self.listings.len(),
+            vocabulary_size: self.vocabulary.len(),
+            average_document_length: if live_documents == 0 {
+                0.0
+            } else {
+                live_length as f32 / live_documents as f32
+            },
+            posting_count: self.listings.values().map(|listing| listing.len()).sum(),
+            storage_bytes: self.listings
+                .values()
+                .map(|listing| listing.stored_blocks() * BLOCKSIZE)
+                .sum(),
+        }
+    }
+
+    /// Estimate of the bytes this index occupies in memory.
+    /// Covers the vocabulary, the listings' bookkeeping, the pages held by
+    /// the page cache and document signatures. Heap memory owned by terms
+    /// themselves (e.g. the contents of a String) is not accounted for.
+    /// Useful to decide when to flush or split an index.
+    pub fn memory_footprint(&self) -> usize {
+        let vocabulary = self.vocabulary.len() * (mem::size_of::<TTerm>() + mem::size_of::<TermId>());
+        let listings = self.listings
+            .values()
+            .map(|listing| mem::size_of::<TermId>() + listing.memory_usage())
+            .sum::<usize>();
+        let signatures = self.signatures
+            .as_ref()
+            .map_or(0, |signatures| {
+                signatures.len() * (mem::size_of::<DocId>() + mem::size_of::<Signature>())
+            });
+        let doc_lengths = self.doc_lengths.len() * (mem::size_of::<DocId>() + mem::size_of::<u32>());
+        mem::size_of::<Self>() + vocabulary + listings + signatures + doc_lengths +
+        self.page_manager.memory_usage()
+    }
+
+    /// Get the sparse (TermId, weight) vector of a document, ordered by
+    /// TermId. As postings do not store term frequencies, the weight of a
+    /// term is its inverse document frequency.
+    ///
+    /// There is no forward index, so every listing has to be seeked for
+    /// `doc_id`. Unknown documents result in an empty vector.
+    pub fn document_vector(&self, doc_id: DocId) -> Vec<(TermId, f32)> {
+        if self.is_deleted(doc_id) {
+            return Vec::new();
+        }
+        self.listings
+            .iter()
+            .filter(|&(_, listing)| {
+                let mut decoder = listing.posting_decoder(&self.page_manager);
+                decoder.next_seek(&Posting(doc_id)).is_some_and(|posting| posting.doc_id() == doc_id)
+            })
+            .map(|(term_id, listing)| {
+                (*term_id, InverseDocumentFrequency::from(self.doc_count, listing.len()).0)
+            })
+            .collect()
+    }
+
+    /// Cosine similarity between the document vectors of `a` and `b`.
+    /// 0.0 if one of them is unknown, deleted or only consists of terms
+    /// occurring in every document.
+    pub fn document_similarity(&self, a: DocId, b: DocId) -> f32 {
+        let vector_a = self.document_vector(a);
+        let vector_b = self.document_vector(b);
+        let norm = |vector: &[(TermId, f32)]| {
+            vector.iter().map(|&(_, weight)| weight * weight).sum::<f32>().sqrt()
+        };
+        let norms = norm(&vector_a) * norm(&vector_b);
+        if norms == 0.0 {
+            return 0.0;
+        }
+        // Both vectors are ordered by TermId
+        let mut dot = 0.0;
+        let mut iter_b = vector_b.iter().peekable();
+        for &(term_id, weight) in &vector_a {
+            while iter_b.peek().is_some_and(|&&(other, _)| other < term_id) {
+                iter_b.next();
+            }
+            if let Some(&&(other, other_weight)) = iter_b.peek() {
+                if other == term_id {
+                    dot += weight * other_weight;
+                }
+            }
+        }
+        dot / norms
+    }
+
+    /// Start computing a MinHash signature for every document indexed from
+    /// now on via `index_document`. Needed for `similar_documents`.
+    pub fn enable_duplicate_detection(&mut self) {
+        if self.signatures.is_none() {
+            self.signatures = Some(BTreeMap::new());
+        }
+    }
+
+    /// Get all other documents whose estimated similarity to `doc_id` is at
+    /// least `threshold` (between 0 and 1), ordered by DocId.
+    ///
+    /// Only documents indexed after `enable_duplicate_detection` was called
+    /// are taken into account.
+    pub fn similar_documents(&self, doc_id: DocId, threshold: f32) -> Vec<(DocId, f32)> {
+        let signatures = match self.signatures {
+            Some(ref signatures) => signatures,
+            None => return Vec::new(),
+        };
+        let signature = match signatures.get(&doc_id) {
+            Some(signature) => signature,
+            None => return Vec::new(),
+        };
+        signatures.iter()
+            .filter(|&(other_id, _)| *other_id != doc_id)
+            .map(|(other_id, other)| (*other_id, signature.similarity(other)))
+            .filter(|&(_, similarity)| similarity >= threshold)
+            .collect()
+    }
 }
 
-impl<TTerm> Index<TTerm>
+impl<TTerm, C> Index<TTerm, C>
     where TTerm: Ord + Hash,
+          C: Compressor,
           SharedVocabulary<TTerm>: for<'r> TermIterator<'r, TTerm>
 {
     pub fn iterate_terms(&self) -> <SharedVocabulary<TTerm> as TermIterator<TTerm>>::TIter {
         self.vocabulary.iterate_terms()
     }
+
+    /// Iterate over all (term, posting) pairs of this index in vocabulary order.
+    /// Only one listing is decoded at a time.
+    pub fn iter_all<'a>(&'a self) -> impl Iterator<Item = (&'a TTerm, Posting)> + 'a
+        where TTerm: 'a
+    {
+        self.vocabulary.iterate_terms().flat_map(move |(term, term_id)| {
+            self.query_term(term_id).1.map(move |posting| (term, posting))
+        })
+    }
+
+    /// Get all DocIds containing at least one term that falls into `range`,
+    /// ordered and without duplicates.
+    /// For string terms this can be used as a prefix search, e.g. `"foo".."fop"`.
+    pub fn query_range<R>(&self, range: R) -> Vec<DocId>
+        where R: RangeBounds<TTerm>
+    {
+        let mut result = self.vocabulary
+            .iterate_terms()
+            .filter(|&(term, _)| range.contains(term))
+            .flat_map(|(_, term_id)| self.query_term(term_id).1)
+            .map(|posting| posting.doc_id())
+            .collect::<Vec<_>>();
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    /// Iterate over the terms this index has listings for in ascending order.
+    /// The vocabulary is not sorted, so this collects and sorts it first.
+    pub fn terms(&self) -> impl Iterator<Item = &TTerm> {
+        let mut terms = self.vocabulary
+            .iterate_terms()
+            .filter(|&(_, term_id)| self.listings.contains_key(term_id))
+            .map(|(term, _)| term)
+            .collect::<Vec<_>>();
+        terms.sort();
+        terms.into_iter()
+    }
+
+    /// Get the Term a TermId was assigned to.
+    /// The first call builds a reverse lookup of the vocabulary, which is
+    /// kept until new terms are added to this index.
+    pub fn term_of_id(&self, term_id: &TermId) -> Option<&TTerm>
+        where TTerm: Clone
+    {
+        self.term_lookup
+            .get_or_init(|| {
+                self.vocabulary
+                    .iterate_terms()
+                    .map(|(term, term_id)| (*term_id, term.clone()))
+                    .collect()
+            })
+            .get(term_id)
+    }
+}
+
+impl<TTerm: Hash + Eq, C: Compressor> Index<TTerm, C> {
+    /// False if documents were indexed since the last `commit`.
+    /// Dropping an uncommitted index loses these postings
+    pub fn is_committed(&self) -> bool {
+        self.listings.values().all(|listing| listing.is_committed())
+    }
+
+    /// Accept that postings indexed since the last `commit` are lost when the
+    /// index is dropped
+    pub fn discard_uncommitted(&mut self) {
+        for listing in self.listings.values_mut() {
+            listing.discard_uncommitted();
+        }
+    }
+}
+
+impl<TTerm: Hash + Eq, C: Compressor> Drop for Index<TTerm, C> {
+    /// Asserts in debug builds that no indexed postings are lost, once for
+    /// the whole index instead of once per listing
+    fn drop(&mut self) {
+        debug_assert!(self.is_committed() || thread::panicking(),
+                      "Index dropped without commit. Uncommitted postings of {} terms are lost",
+                      self.listings.values().filter(|listing| !listing.is_committed()).count());
+    }
 }
 
 
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+    use std::mem;
+    use std::thread;
+
+    use utils::seeking_iterator::SeekingIterator;
+
     use test_utils::create_test_dir;
 
-    use super::Index;
+    use super::{Index, IndexStatistics};
+    use compressor::VByteCompressor;
     use index::posting::{Posting, DocId};
-    use index::vocabulary::SharedVocabulary;
-    use page_manager::{FsPageManager, RamPageCache};
+    use index::vocabulary::{SharedVocabulary, TermId};
+    use page_manager::{FsPageManager, RamPageCache, Page, BLOCKSIZE};
 
     fn new_index(name: &str) -> Index<usize> {
         let path = &create_test_dir(format!("index/{}", name).as_str());
@@ -214,6 +841,16 @@ mod tests {
                    vec![Posting(DocId(0)), Posting(DocId(1))]);
         assert_eq!((index.query_atom(&150).1).collect::<Vec<_>>(),
                    vec![Posting(DocId(1))]);
+        // Documents indexed term by term are counted like whole documents
+        assert_eq!(index.document_count(), 2);
+        index.delete_document(DocId(0));
+        assert_eq!(index.live_document_count(), 1);
+        index.compact();
+        assert_eq!(index.document_count(), 1);
+        assert_eq!(index.live_document_count(), 1);
+        let stats = index.statistics();
+        assert_eq!(stats.document_count, 1);
+        assert_eq!(stats.average_document_length, 2.0);
     }
 
     #[test]
@@ -296,6 +933,607 @@ mod tests {
         let mut terms = index.iterate_terms().map(|(term, _)| term.clone()).collect::<Vec<_>>();
         terms.sort();
         assert_eq!(terms, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        index.commit();
+    }
+
+    #[test]
+    fn rebuild() {
+        let mut index = new_index("rebuild");
+        for i in 0..200 {
+            index.index_document(i..i + 200, None);
+        }
+        index.commit();
+        index.rebuild();
+        assert_eq!((index.query_atom(&99).1).collect::<Vec<_>>(),
+                   (0..100).map(|i| Posting(DocId(i))).collect::<Vec<_>>());
+        index.index_document(0..400, None);
+        index.commit();
+        assert_eq!((index.query_atom(&0).1).collect::<Vec<_>>(),
+                   vec![Posting(DocId(0)), Posting(DocId(200))]);
+    }
+
+    #[test]
+    fn postings_until() {
+        let mut index = new_index("postings_until");
+        for i in 0..200 {
+            index.index_document(i..i + 200, None);
+        }
+        index.commit();
+        assert_eq!(index.postings_until(&150, DocId(1)),
+                   vec![Posting(DocId(0)), Posting(DocId(1))]);
+        assert_eq!(index.postings_until(&150, DocId(500)).len(), 151);
+        assert_eq!(index.postings_until(&1000, DocId(500)), vec![]);
+    }
+
+    #[test]
+    fn doc_id_deltas() {
+        let mut index = new_index("doc_id_deltas");
+        for i in 0..100 {
+            index.index_document((i % 7)..(i % 7) + 3, None);
+        }
+        index.commit();
+        // Term 2 is in all documents with i % 7 in 0..3
+        assert_eq!(index.doc_id_deltas(&2).take(6).collect::<Vec<_>>(),
+                   vec![1, 1, 5, 1, 1, 5]);
+        assert_eq!(index.doc_id_deltas(&2).count(), index.term_df(&TermId(2)) - 1);
+        assert_eq!(index.doc_id_deltas(&1000).count(), 0);
+    }
+
+    #[test]
+    fn refine() {
+        let mut index = new_index("refine");
+        for i in 0..2000 {
+            index.index_document((i % 7)..(i % 7) + 3, None);
+        }
+        index.commit();
+        // Term 8 is in all documents with i % 7 == 6
+        assert_eq!(index.refine(&[DocId(0), DocId(6), DocId(7), DocId(13), DocId(1994)], &8),
+                   vec![DocId(6), DocId(13), DocId(1994)]);
+        assert_eq!(index.refine(&[DocId(6), DocId(2000)], &8), vec![DocId(6)]);
+        assert_eq!(index.refine(&[DocId(6)], &100), vec![]);
+    }
+
+    #[test]
+    fn listing_length_histogram() {
+        let mut index = new_index("listing_length_histogram");
+        for i in 0..200 {
+            index.index_document(i..i + 200, None);
+        }
+        index.commit();
+        // Terms 0..200 occur in 1..=200 documents, terms 200..399 in 199..=1
+        assert_eq!(index.listing_length_histogram(&[1, 10, 100]),
+                   vec![2, 18, 180, 199]);
+        assert_eq!(index.listing_length_histogram(&[]), vec![399]);
+    }
+
+    #[test]
+    fn delete_document() {
+        let mut index = new_index("delete_document");
+        for i in 0..100 {
+            index.index_document(i..i + 10, None);
+        }
+        index.commit();
+        index.delete_document(DocId(5));
+        index.delete_document(DocId(7));
+        // No-ops
+        index.delete_document(DocId(5));
+        index.delete_document(DocId(1000));
+        assert_eq!(index.live_document_count(), 98);
+        assert_eq!(index.statistics().document_count, 100);
+        // Explicit DocIds leave gaps. Ids in between were never indexed
+        let mut gaps = new_index("delete_document_gaps");
+        gaps.index_document(0..3, Some(DocId(0)));
+        gaps.index_document(0..3, Some(DocId(10)));
+        gaps.commit();
+        gaps.delete_document(DocId(5));
+        assert!(!gaps.is_deleted(DocId(5)));
+        assert_eq!(gaps.live_document_count(), 2);
+        gaps.compact();
+        assert_eq!(gaps.document_count(), 2);
+        assert_eq!(index.query_atom(&9).1.collect::<Vec<_>>(),
+                   vec![0, 1, 2, 3, 4, 6, 8, 9].into_iter().map(|i| Posting(DocId(i))).collect::<Vec<_>>());
+        assert_eq!(index.refine(&[DocId(6), DocId(7), DocId(8)], &9),
+                   vec![DocId(6), DocId(8)]);
+        assert!(index.is_deleted(DocId(7)));
+        assert!(!index.is_deleted(DocId(8)));
+        assert_eq!(index.document_vector(DocId(5)), vec![]);
+        // Deleted documents are still hidden after further indexing
+        index.index_document(0..10, None);
+        index.commit();
+        assert_eq!(index.query_atom(&7).1.count(), 7);
+    }
+
+    #[test]
+    fn document_frequency() {
+        let mut index = new_index("document_frequency");
+        index.index_document(vec![0, 1, 2].into_iter(), None);
+        index.index_document(vec![0, 2].into_iter(), None);
+        index.index_document(vec![0, 16].into_iter(), None);
+        index.commit();
+        assert_eq!(index.document_count(), 3);
+        assert_eq!(index.term_count(), 4);
+        assert_eq!(index.document_frequency(&0), 3);
+        assert_eq!(index.document_frequency(&2), 2);
+        assert_eq!(index.document_frequency(&16), 1);
+        assert_eq!(index.document_frequency(&17), 0);
+    }
+
+    #[test]
+    fn commit_durable() {
+        let mut index = new_index("commit_durable");
+        for i in 0..100 {
+            index.index_document(i..i + 20, None);
+        }
+        index.commit_durable();
+        assert_eq!(index.query_atom(&50).1.count(), 20);
+    }
+
+    #[test]
+    fn term_idf() {
+        let mut index = new_index("term_idf");
+        index.index_document(0..10, None);
+        index.index_document(5..15, None);
+        index.commit();
+        assert_eq!(index.term_idf(&0), Some((2.0f32 / 1.0).ln()));
+        assert_eq!(index.term_idf(&5), Some(0.0));
+        // Cached
+        assert_eq!(index.term_idf(&0), Some((2.0f32 / 1.0).ln()));
+        assert_eq!(index.term_idf(&100), None);
+        index.index_document(0..3, None);
+        index.commit();
+        assert_eq!(index.term_idf(&0), Some((3.0f32 / 2.0).ln()));
+        assert_eq!(index.term_idf(&5), Some((3.0f32 / 2.0).ln()));
+        assert_eq!(index.term_idf(&10), Some((3.0f32 / 1.0).ln()));
+    }
+
+    #[test]
+    fn compact_step_idf() {
+        let mut index = new_index("compact_step_idf");
+        for i in 0..100 {
+            index.index_document((i % 7)..(i % 7) + 10, None);
+        }
+        index.commit();
+        for i in (0..100).filter(|i| i % 4 == 0) {
+            index.delete_document(DocId(i));
+        }
+        loop {
+            for term in 0..16 {
+                // Reading caches the idf
+                let df = index.document_frequency(&term) as f32;
+                let expected = (index.document_count() as f32 / df).ln();
+                assert_eq!(index.term_idf(&term), Some(expected));
+            }
+            if !index.compact_step() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn index_documents() {
+        let mut index = new_index("index_documents");
+        let mut reports = Vec::new();
+        let doc_ids = index.index_documents((0..25).map(|i| i * 2..i * 2 + 10),
+                                            10,
+                                            |docs, terms| reports.push((docs, terms)));
+        assert_eq!(doc_ids, (0..25).map(DocId).collect::<Vec<_>>());
+        assert_eq!(reports, vec![(10, 28), (20, 48), (25, 58)]);
+        assert_eq!(index.term_count(), 58);
+        index.commit();
+    }
+
+    #[test]
+    fn document_length() {
+        let mut index = new_index("document_length");
+        index.index_document(0..10, None);
+        index.index_document(vec![1, 2, 1, 1, 3, 4, 5, 6, 7, 1].into_iter(), None);
+        index.index_document(vec![0, 0, 0, 0, 0, 0].into_iter(), Some(DocId(5)));
+        index.index_term(DocId(6), 3);
+        index.index_term(DocId(6), 4);
+        assert_eq!(index.document_length(DocId(0)), Some(10));
+        assert_eq!(index.document_length(DocId(1)), Some(10));
+        assert_eq!(index.document_length(DocId(5)), Some(6));
+        assert_eq!(index.document_length(DocId(6)), Some(2));
+        assert_eq!(index.document_length(DocId(2)), None);
+        index.delete_document(DocId(1));
+        assert_eq!(index.document_length(DocId(1)), None);
+        index.commit();
+    }
+
+    #[test]
+    fn merge() {
+        let mut single = new_index("merge_single");
+        let mut a = new_index("merge_a");
+        let mut b = new_index("merge_b");
+        for i in 0..300 {
+            let document = (i % 13)..(i % 13) + 20;
+            single.index_document(document.clone(), None);
+            if i < 120 {
+                a.index_document(document, None);
+            } else {
+                b.index_document(document, None);
+            }
+        }
+        // A term only known to b
+        single.index_document(vec![100].into_iter(), None);
+        b.index_document(vec![100].into_iter(), None);
+        single.commit();
+        a.commit();
+        b.commit();
+        a.merge(b);
+        assert_eq!(a.document_count(), single.document_count());
+        assert_eq!(a.term_count(), single.term_count());
+        for term in 0..101 {
+            assert_eq!(a.query_atom(&term).1.collect::<Vec<_>>(),
+                       single.query_atom(&term).1.collect::<Vec<_>>());
+        }
+        assert_eq!(a.document_length(DocId(300)), Some(1));
+        // Merged index can be extended further
+        let doc_id = a.index_document(vec![100].into_iter(), None);
+        a.commit();
+        assert_eq!(doc_id, DocId(301));
+        assert_eq!(a.query_atom(&100).1.count(), 2);
+    }
+
+    #[test]
+    fn drop_uncommitted() {
+        let mut index = new_index("drop_uncommitted");
+        assert!(index.is_committed());
+        for i in 0..20 {
+            index.index_document(i..i + 5, None);
+        }
+        assert!(!index.is_committed());
+        index.commit();
+        assert!(index.is_committed());
+        index.index_document(0..5, None);
+        index.discard_uncommitted();
+        // Dropping it now loses the postings, but was asked for
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Index dropped without commit")]
+    fn drop_uncommitted_asserts() {
+        let mut index = new_index("drop_uncommitted_asserts");
+        for i in 0..20 {
+            index.index_document(i..i + 5, None);
+        }
+    }
+
+    #[test]
+    fn merge_uncommitted() {
+        let mut a = new_index("merge_uncommitted_a");
+        let mut b = new_index("merge_uncommitted_b");
+        for i in 0..50 {
+            a.index_document(vec![0, 1].into_iter(), None);
+            b.index_document(vec![i % 5, 1].into_iter(), None);
+        }
+        a.commit();
+        a.merge(b);
+        assert_eq!(a.document_count(), 100);
+        assert_eq!(a.query_atom(&0).1.count(), 60);
+        assert_eq!(a.query_atom(&1).1.count(), 100);
+        assert_eq!(a.query_atom(&4).1.collect::<Vec<_>>(),
+                   (0..10).map(|i| Posting(DocId(54 + i * 5))).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn prune_rare_terms() {
+        let mut index = new_index("prune_rare_terms");
+        index.set_inline_limit(0);
+        index.index_document(0..10, None);
+        index.index_document(5..15, None);
+        for _ in 0..100 {
+            index.index_document(20..22, None);
+        }
+        index.commit();
+        assert_eq!(index.prune_rare_terms(2), 10);
+        assert_eq!(index.term_count(), 7);
+        assert_eq!(index.query_atom(&0).1.count(), 0);
+        assert_eq!(index.query_atom(&14).1.count(), 0);
+        assert_eq!(index.query_atom(&5).1.collect::<Vec<_>>(),
+                   vec![Posting(DocId(0)), Posting(DocId(1))]);
+        assert_eq!(index.query_atom(&21).1.count(), 100);
+        // Pruned terms keep their TermId
+        assert_eq!(index.get_term_id(&0), Some(TermId(0)));
+        // Pages of pruned listings are reused
+        index.index_document(30..50, None);
+        index.commit();
+        assert_eq!(index.query_atom(&40).1.collect::<Vec<_>>(), vec![Posting(DocId(102))]);
+        assert_eq!(index.query_atom(&21).1.count(), 100);
+        assert_eq!(index.prune_rare_terms(0), 0);
+    }
+
+    #[test]
+    fn compact_step() {
+        fn build(name: &str) -> Index<usize> {
+            let mut index = new_index(name);
+            for i in 0..200 {
+                index.index_document((i % 11)..(i % 11) + 15, None);
+            }
+            index.index_document(vec![50].into_iter(), None);
+            index.commit();
+            for i in (0..200).filter(|i| i % 3 == 0) {
+                index.delete_document(DocId(i));
+            }
+            index.delete_document(DocId(200));
+            index
+        }
+        let mut stepwise = build("compact_step_stepwise");
+        let mut oneshot = build("compact_step_oneshot");
+        let before = stepwise.query_atom(&12).1.collect::<Vec<_>>();
+
+        let mut steps = 0;
+        while stepwise.compact_step() {
+            steps += 1;
+            if steps == 5 {
+                // Restarts compaction
+                stepwise.delete_document(DocId(1));
+                oneshot.delete_document(DocId(1));
+            }
+        }
+        oneshot.compact();
+        assert_eq!(steps, 5 + stepwise.term_count() + 1);
+        assert!(!stepwise.compact_step());
+
+        assert_eq!(stepwise.document_count(), 200 - 67 - 1);
+        assert_eq!(stepwise.live_document_count(), stepwise.document_count());
+        assert_eq!(stepwise.statistics(), oneshot.statistics());
+        // Term 50 only occurred in a deleted document
+        assert_eq!(stepwise.document_frequency(&50), 0);
+        for term in 0..26 {
+            assert_eq!(stepwise.query_atom(&term).1.collect::<Vec<_>>(),
+                       oneshot.query_atom(&term).1.collect::<Vec<_>>());
+        }
+        assert_eq!(stepwise.query_atom(&12).1.collect::<Vec<_>>(),
+                   before.into_iter().filter(|posting| posting.doc_id() != DocId(1)).collect::<Vec<_>>());
+        assert_eq!(stepwise.statistics().posting_count,
+                   stepwise.iter_all().count());
+    }
+
+    #[test]
+    fn compact_redelete() {
+        let mut index = new_index("compact_redelete");
+        for _ in 0..10 {
+            index.index_document(0..3, None);
+        }
+        index.commit();
+        index.delete_document(DocId(5));
+        index.compact();
+        assert_eq!(index.document_count(), 9);
+        // The document is gone. Deleting it again must not count it twice
+        index.delete_document(DocId(5));
+        assert!(!index.is_deleted(DocId(5)));
+        index.compact();
+        assert_eq!(index.document_count(), 9);
+        assert_eq!(index.live_document_count(), 9);
+    }
+
+    #[test]
+    fn compact_during_merge() {
+        let mut a = new_index("compact_during_merge_a");
+        let mut b = new_index("compact_during_merge_b");
+        for _ in 0..20 {
+            a.index_document(0..5, None);
+            b.index_document(0..5, None);
+        }
+        a.commit();
+        a.delete_document(DocId(2));
+        b.delete_document(DocId(3));
+        // Compact some listings of a, then merge b's tombstone into them
+        assert!(a.compact_step());
+        assert!(a.compact_step());
+        a.merge(b);
+        a.compact();
+        for term in 0..5 {
+            let doc_ids = a.query_atom(&term).1.map(|posting| posting.doc_id()).collect::<Vec<_>>();
+            assert_eq!(doc_ids.len(), 38);
+            assert!(!doc_ids.contains(&DocId(2)));
+            assert!(!doc_ids.contains(&DocId(23)));
+        }
+        assert_eq!(a.document_count(), 38);
+    }
+
+    #[test]
+    fn concurrent_queries() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Index<String>>();
+
+        let mut index = new_index("concurrent_queries");
+        for i in 0..2000 {
+            index.index_document((i % 50)..(i % 50) + 30, None);
+        }
+        index.commit();
+        let baseline = (0..80).map(|term| index.query_atom(&term).1.collect::<Vec<_>>()).collect::<Vec<_>>();
+        let index = Arc::new(index);
+        let baseline = Arc::new(baseline);
+        let threads = (0..16)
+            .map(|t| {
+                let index = index.clone();
+                let baseline = baseline.clone();
+                thread::spawn(move || for round in 0..20 {
+                    for term in 0..80 {
+                        // Every thread walks the terms in a different order
+                        let term = (term * 7 + t * 13 + round) % 80;
+                        assert_eq!(index.query_atom(&term).1.collect::<Vec<_>>(), baseline[term]);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn statistics() {
+        let mut index = new_index("statistics");
+        index.set_inline_limit(0);
+        index.index_document(0..10, None);
+        index.index_document(5..20, None);
+        index.index_document(20..22, None);
+        index.commit();
+        index.delete_document(DocId(2));
+        let statistics = index.statistics();
+        assert_eq!(statistics,
+                   IndexStatistics {
+                       document_count: 3,
+                       live_document_count: 2,
+                       term_count: 22,
+                       vocabulary_size: 22,
+                       // (10 + 15) / 2. The deleted document is not counted
+                       average_document_length: 12.5,
+                       posting_count: 27,
+                       // Every listing fits into a single block
+                       storage_bytes: 22 * BLOCKSIZE,
+                   });
+    }
+
+    #[test]
+    fn memory_footprint() {
+        let mut index = new_index("memory_footprint");
+        let mut last = index.memory_footprint();
+        for i in 0..100 {
+            index.index_document(i..i + 50, None);
+            let footprint = index.memory_footprint();
+            assert!(footprint >= last);
+            last = footprint;
+        }
+        index.commit();
+        assert!(index.memory_footprint() > 0);
+    }
+
+    #[test]
+    fn document_vector() {
+        let mut index = new_index("document_vector");
+        index.index_document(0..10, None);
+        index.index_document(5..20, None);
+        index.index_document(15..30, None);
+        index.commit();
+        let vector = index.document_vector(DocId(1));
+        assert_eq!(vector.iter().map(|&(term_id, _)| term_id).collect::<Vec<_>>(),
+                   (5..20).map(TermId).collect::<Vec<_>>());
+        assert!(vector.iter().all(|&(_, weight)| weight > 0.0));
+        // Terms in fewer documents weigh more
+        assert!(vector[0].1 < vector[5].1);
+        assert!(vector[14].1 < vector[9].1);
+        assert_eq!(index.document_vector(DocId(3)), Vec::new());
+    }
+
+    #[test]
+    fn document_similarity() {
+        let mut index = new_index("document_similarity");
+        index.index_document(0..10, None);
+        index.index_document(0..10, None);
+        index.index_document(5..15, None);
+        index.index_document(20..30, None);
+        index.commit();
+        assert!((index.document_similarity(DocId(0), DocId(1)) - 1.0).abs() < 1e-6);
+        let overlapping = index.document_similarity(DocId(0), DocId(2));
+        assert!(overlapping > 0.0 && overlapping < 1.0);
+        assert_eq!(index.document_similarity(DocId(2), DocId(0)), overlapping);
+        assert_eq!(index.document_similarity(DocId(0), DocId(3)), 0.0);
+        assert_eq!(index.document_similarity(DocId(0), DocId(10)), 0.0);
+        index.delete_document(DocId(1));
+        assert_eq!(index.document_similarity(DocId(0), DocId(1)), 0.0);
+    }
+
+    #[test]
+    fn similar_documents() {
+        let mut index = new_index("similar_documents");
+        index.enable_duplicate_detection();
+        index.index_document(0..100, None);
+        index.index_document(1000..1100, None);
+        index.index_document(0..95, None);
+        index.commit();
+
+        let similar = index.similar_documents(DocId(0), 0.8);
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].0, DocId(2));
+        assert!(index.similar_documents(DocId(1), 0.8).is_empty());
+        assert!(index.similar_documents(DocId(3), 0.0).is_empty());
+    }
+
+    #[test]
+    fn merge_similar_documents() {
+        let mut a = new_index("merge_similar_documents_a");
+        let mut b = new_index("merge_similar_documents_b");
+        a.enable_duplicate_detection();
+        b.enable_duplicate_detection();
+        a.index_document(0..100, None);
+        a.index_document(1000..1100, None);
+        // b assigns its TermIds in a different order
+        b.index_document(2000..2100, None);
+        b.index_document(0..95, None);
+        b.index_document((0..100).rev(), None);
+        a.merge(b);
+
+        let similar = a.similar_documents(DocId(0), 0.8);
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].0, DocId(3));
+        assert!(a.similar_documents(DocId(2), 0.8).is_empty());
+        assert!(a.similar_documents(DocId(1), 0.8).is_empty());
+    }
+
+    #[test]
+    fn iter_all() {
+        let mut index = new_index("iter_all");
+        for i in 0..200 {
+            index.index_document(i..i + 200, None);
+        }
+        index.commit();
+        assert_eq!(index.iter_all().count(), index.statistics().posting_count);
+        assert_eq!(index.iter_all().filter(|&(term, _)| *term == 398).collect::<Vec<_>>(),
+                   vec![(&398, Posting(DocId(199)))]);
+    }
+
+    #[test]
+    fn query_range() {
+        let mut index = new_index("query_range");
+        index.index_document(vec![1, 2].into_iter(), None);
+        index.index_document(vec![3].into_iter(), None);
+        index.index_document(vec![5, 6].into_iter(), None);
+        index.index_document(vec![4, 7].into_iter(), None);
+        index.commit();
+        assert_eq!(index.query_range(2..5), vec![DocId(0), DocId(1), DocId(3)]);
+        assert_eq!(index.query_range(6..), vec![DocId(2), DocId(3)]);
+        assert_eq!(index.query_range(10..20), vec![]);
+    }
+
+    #[test]
+    fn terms() {
+        let mut index = new_index("terms");
+        index.index_document(vec![99].into_iter(), None);
+        for i in 0..15 {
+            index.index_document(vec![14 - i, (i * 2) % 15].into_iter(), None);
+        }
+        index.commit();
+        assert!(index.contains_term(&99));
+        // Every term but 99 is in two documents
+        index.prune_rare_terms(2);
+        assert!(index.contains_term(&7));
+        assert!(!index.contains_term(&99));
+        assert!(!index.contains_term(&100));
+        assert_eq!(index.terms().cloned().collect::<Vec<_>>(), (0..15).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn term_of_id() {
+        let mut index = new_index("term_of_id");
+        index.index_document(0..10, Some(DocId(0)));
+        for (term, term_id) in index.iterate_terms() {
+            assert_eq!(index.get_term_id(term), Some(*term_id));
+            assert_eq!(index.term_of_id(term_id), Some(term));
+        }
+        assert_eq!(index.term_of_id(&TermId(10)), None);
+        // Growing the vocabulary invalidates the reverse lookup
+        index.index_document(5..15, Some(DocId(1)));
+        let term_id = index.get_term_id(&14).unwrap();
+        assert_eq!(index.term_of_id(&term_id), Some(&14));
+        index.index_term(DocId(2), 20);
+        let term_id = index.get_term_id(&20).unwrap();
+        assert_eq!(index.term_of_id(&term_id), Some(&20));
+        index.commit();
     }
 
     #[test]
@@ -311,4 +1549,53 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn vbyte_compressor() {
+        let path = &create_test_dir("index/vbyte_compressor");
+        let pmgr = FsPageManager::new(&path.join("pages.bin"));
+        let mut vbyte = Index::<usize, VByteCompressor>::with_compressor(RamPageCache::new(pmgr),
+                                                                        SharedVocabulary::new());
+        let mut naive = new_index("vbyte_compressor_naive");
+        for i in 0..1000 {
+            vbyte.index_document(i % 7..i % 7 + 5, None);
+            naive.index_document(i % 7..i % 7 + 5, None);
+        }
+        vbyte.commit();
+        naive.commit();
+        for term in 0..12 {
+            assert_eq!(vbyte.query_atom(&term).1.collect::<Vec<_>>(),
+                       naive.query_atom(&term).1.collect::<Vec<_>>());
+        }
+        assert!(vbyte.statistics().storage_bytes < naive.statistics().storage_bytes);
+    }
+
+    #[test]
+    fn tiny_page_cache() {
+        let path = &create_test_dir("index/tiny_page_cache");
+        let pmgr = FsPageManager::new(&path.join("pages.bin"));
+        let mut index = Index::<usize>::new(RamPageCache::with_capacity(pmgr, 1),
+                                            SharedVocabulary::new());
+        for i in 0..3000 {
+            index.index_document((0..5).map(|t| t * (i % 3 + 1)), None);
+        }
+        index.commit();
+        // Listings span many pages and share one cached page. Scans
+        // interleaved by next_seek evict each other's pages constantly
+        let decoder = |atom| {
+            index.listings[&index.get_term_id(&atom).unwrap()].posting_decoder(&index.page_manager)
+        };
+        let mut fours = decoder(4);
+        let mut count = 0;
+        for posting in decoder(2) {
+            if fours.next_seek(&posting) == Some(posting) {
+                count += 1;
+            }
+        }
+        assert_eq!(count, 2000);
+        assert_eq!(index.query_atom(&0).1.count(), 3000);
+        assert_eq!(index.query_atom(&12).1.map(|p| (p.0).0).collect::<Vec<_>>(),
+                   (0..3000).filter(|i| i % 3 == 2).collect::<Vec<_>>());
+        assert!(index.page_manager.memory_usage() <= mem::size_of::<Page>());
+    }
 }
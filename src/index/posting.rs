@@ -1,6 +1,8 @@
+use std::collections::BTreeSet;
+
 use compressor::Compressor;
-use page_manager::BlockIter;
-use utils::ring_buffer::BiasedRingBuffer;
+use page_manager::{Block, BlockIter};
+use utils::ring_buffer::{BiasedRingBuffer, SIZE};
 use utils::Baseable;
 use utils::seeking_iterator::SeekingIterator;
 use utils::progress::Progress;
@@ -65,31 +67,61 @@ impl<'a> Baseable<&'a Posting> for Posting {
 
 /// Wraps the Decoder around an enum.
 /// For the possibility of an empty decoder
+/// or a decoder whose deleted documents have to be skipped
 #[derive(Clone, Debug)]
 pub enum PostingIterator<'a> {
     Empty,
     Decoder(PostingDecoder<'a>),
+    Filtered(PostingDecoder<'a>, &'a BTreeSet<DocId>),
 }
 
 /// Takes a block iterator and a list of biases and iterates over the resulting
 /// postings
 #[derive(Clone, Debug)]
-pub struct PostingDecoder<'a> {
-    posting_buffer: BiasedRingBuffer<Posting>,
+pub struct PostingDecoder<'a, const N: usize = SIZE> {
+    posting_buffer: BiasedRingBuffer<Posting, N>,
     bias_list: &'a [Posting],
+    /// Index of the next block to decode in `blocks` and `bias_list`
+    next_block: usize,
     blocks: BlockIter<'a>,
     pos: u32,
     len: u32,
+    decompress: fn(Block, &mut BiasedRingBuffer<Posting, N>),
 }
 
-impl<'a> PostingDecoder<'a> {
+impl<'a, const N: usize> PostingDecoder<'a, N> {
     pub fn new(blocks: BlockIter<'a>, bias_list: &'a [Posting], len: u32) -> Self {
         PostingDecoder {
             blocks: blocks,
             bias_list: bias_list,
-            posting_buffer: BiasedRingBuffer::new(),
+            next_block: 0,
+            posting_buffer: BiasedRingBuffer::default(),
             pos: 0,
-            len: len
+            len: len,
+            decompress: UsedCompressor::decompress,
+        }
+    }
+
+    /// Decode blocks that were not encoded by `UsedCompressor`,
+    /// e.g. `with_decompressor(VByteCompressor::decompress)`
+    pub fn with_decompressor(mut self,
+                             decompress: fn(Block, &mut BiasedRingBuffer<Posting, N>))
+                             -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Decoder for postings that were never shipped to a block.
+    /// `blocks` is expected to be empty.
+    pub fn inline(blocks: BlockIter<'a>, postings: BiasedRingBuffer<Posting, N>, len: u32) -> Self {
+        PostingDecoder {
+            blocks,
+            bias_list: &[],
+            next_block: 0,
+            posting_buffer: postings,
+            pos: 0,
+            len,
+            decompress: UsedCompressor::decompress,
         }
     }
 
@@ -111,6 +143,9 @@ impl<'a> Iterator for PostingIterator<'a> {
         match *self {
             PostingIterator::Empty => None,
             PostingIterator::Decoder(ref mut decoder) => decoder.next(),
+            PostingIterator::Filtered(ref mut decoder, deleted) => {
+                decoder.find(|posting| !deleted.contains(&posting.doc_id()))
+            }
         }
     }
 
@@ -118,24 +153,23 @@ impl<'a> Iterator for PostingIterator<'a> {
         match *self {
             PostingIterator::Empty => (0, Some(0)),
             PostingIterator::Decoder(ref decoder) => decoder.size_hint(),
+            // How many of the remaining postings are deleted is unknown
+            PostingIterator::Filtered(ref decoder, _) => (0, decoder.size_hint().1),
         }
     }
 }
 
-impl<'a> ExactSizeIterator for PostingIterator<'a> {}
+impl<'a, const N: usize> ExactSizeIterator for PostingDecoder<'a, N> {}
 
-impl<'a> ExactSizeIterator for PostingDecoder<'a> {}
-
-impl<'a> Iterator for PostingDecoder<'a> {
+impl<'a, const N: usize> Iterator for PostingDecoder<'a, N> {
     type Item = Posting;
 
     fn next(&mut self) -> Option<Posting> {
         if self.posting_buffer.is_empty() {
             if let Some(block) = self.blocks.next() {
-                let (bias, rest) = self.bias_list.split_first().unwrap();
-                self.bias_list = rest;
-                self.posting_buffer.set_base(*bias);
-                UsedCompressor::decompress(block, &mut self.posting_buffer);
+                self.posting_buffer.set_base(self.bias_list[self.next_block]);
+                self.next_block += 1;
+                (self.decompress)(block, &mut self.posting_buffer);
             }
         }
         self.pos += 1;
@@ -152,12 +186,13 @@ impl<'a> Iterator for PostingDecoder<'a> {
 }
 
 
-impl<'a> SeekingIterator for PostingDecoder<'a> {
+impl<'a, const N: usize> SeekingIterator for PostingDecoder<'a, N> {
     type Item = Posting;
 
     fn next_seek(&mut self, other: &Self::Item) -> Option<Self::Item> {
         // Check in what block we have to seek to
-        let index = match self.bias_list.binary_search(other) {
+        let remaining_biases = &self.bias_list[self.next_block..];
+        let index = match remaining_biases.binary_search(other) {
             Err(index) => index,
             Ok(index) => index,
         };
@@ -170,11 +205,14 @@ impl<'a> SeekingIterator for PostingDecoder<'a> {
             // Flush posting buffer
             self.pos += self.posting_buffer.count() as u32;
             self.posting_buffer.flush();
-            // Get block
+            // Jump straight to the block whose bias bounds `other`
             if index > 1 {
-                self.pos += (index as u32 - 1u32) * 16u32;
-                self.blocks.skip_blocks(index - 1);
-                self.bias_list = &self.bias_list[index - 1..];
+                // How many postings a block holds depends on the compressor.
+                // Assume the skipped blocks hold their share of the rest
+                let remaining = self.len.saturating_sub(self.pos) as u64;
+                self.pos += (remaining * (index as u64 - 1) / remaining_biases.len() as u64) as u32;
+                self.next_block += index - 1;
+                self.blocks.seek_to_block(self.next_block);
             }
         }
         loop {
@@ -368,4 +406,21 @@ mod tests {
         assert_eq!(decoder.next(), Some(Posting(DocId(99_999))));
     }
 
+
+    #[test]
+    fn seek_far() {
+        let mut cache = new_cache("seek_far");
+        let mut listing = Listing::new();
+        let postings = (0..20000).map(|i| Posting(DocId(i * 3))).collect::<Vec<_>>();
+        listing.add(&postings, &mut cache);
+        listing.commit(&mut cache);
+        let mut decoder = listing.posting_decoder(&cache);
+        // Jumps over several pages, then decodes linearly from there
+        assert_eq!(decoder.next_seek(&Posting(DocId(31_000))), Some(Posting(DocId(31_002))));
+        assert_eq!(decoder.collect::<Vec<_>>(), &postings[10335..]);
+        let mut decoder = listing.posting_decoder(&cache);
+        assert_eq!(decoder.next_seek(&Posting(DocId(900))), Some(Posting(DocId(900))));
+        assert_eq!(decoder.next_seek(&Posting(DocId(59_997))), Some(Posting(DocId(59_997))));
+        assert_eq!(decoder.next(), None);
+    }
 }
@@ -1,6 +1,10 @@
+use std::cmp;
 use std::hash::Hash;
+use std::io::{self, Read, Write};
 use std::collections::HashMap;
-use std::collections::hash_map::Iter;
+use std::collections::hash_map::{Iter, IntoIter};
+
+use utils::vbyte::{vbyte_encode, VByteDecoder};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 pub struct TermId(pub u64);
@@ -12,6 +16,24 @@ impl<TTerm: Hash + Eq> SharedVocabulary<TTerm> {
     pub fn new() -> Self {
         SharedVocabulary(HashMap::new())
     }
+
+    /// Number of terms in this vocabulary
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<TTerm: Hash + Eq> IntoIterator for SharedVocabulary<TTerm> {
+    type Item = (TTerm, TermId);
+    type IntoIter = IntoIter<TTerm, TermId>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
 }
 
 pub trait TermIterator<'a, TTerm: 'a> {
@@ -24,6 +46,53 @@ pub trait Vocabulary<TTerm> {
     fn get(&self, &TTerm) -> Option<TermId>;
 }
 
+/// Vocabularies that can be written to and read back from bytes.
+/// The exact `TermId` of every term survives the round trip, so postings
+/// stored under these ids stay valid.
+pub trait PersistentVocabulary: Sized {
+    fn save<W: Write>(&self, target: &mut W) -> io::Result<()>;
+    fn load<R: Read>(source: &mut R) -> io::Result<Self>;
+}
+
+/// Terms that a `PersistentVocabulary` can save
+pub trait ByteEncodable {
+    fn encode(&self) -> Vec<u8>;
+}
+
+/// Terms that a `PersistentVocabulary` can load. `bytes` are exactly the
+/// bytes returned by `ByteEncodable::encode`
+pub trait ByteDecodable: Sized {
+    fn decode(bytes: &[u8]) -> io::Result<Self>;
+}
+
+impl ByteEncodable for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ByteDecodable for String {
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        String::from_utf8(bytes.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl ByteEncodable for usize {
+    fn encode(&self) -> Vec<u8> {
+        vbyte_encode(*self)
+    }
+}
+
+impl ByteDecodable for usize {
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut decoder = VByteDecoder::new(bytes.iter().cloned());
+        match decoder.next() {
+            Some(number) if decoder.bytes_consumed() == bytes.len() => Ok(number),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid vbyte encoded term")),
+        }
+    }
+}
+
 impl<'a, TTerm: 'a + Hash + Eq> TermIterator<'a, TTerm> for SharedVocabulary<TTerm> {
     type TIter = Iter<'a, TTerm, TermId>;
 
@@ -67,3 +136,140 @@ impl<TTerm> Vocabulary<TTerm> for HashMap<TTerm, TermId> where TTerm: Hash + Eq{
         self.get(term).cloned()
     }
 }
+
+/// Layout: number of terms, then for every term the length of its encoding,
+/// its encoding and its id. All numbers are variable byte encoded.
+impl<TTerm> PersistentVocabulary for HashMap<TTerm, TermId>
+    where TTerm: Hash + Eq + ByteEncodable + ByteDecodable
+{
+    fn save<W: Write>(&self, target: &mut W) -> io::Result<()> {
+        let mut bytes = vbyte_encode(self.len());
+        for (term, term_id) in self {
+            let term = term.encode();
+            bytes.extend(vbyte_encode(term.len()));
+            bytes.extend(term);
+            bytes.extend(vbyte_encode(term_id.0 as usize));
+        }
+        target.write_all(&bytes)
+    }
+
+    fn load<R: Read>(source: &mut R) -> io::Result<Self> {
+        fn truncated() -> io::Error {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "truncated vocabulary")
+        }
+        /// Decodes the number at `pos` and moves `pos` behind it
+        fn read_number(bytes: &[u8], pos: &mut usize) -> io::Result<usize> {
+            let mut decoder = VByteDecoder::new(bytes[*pos..].iter().cloned());
+            let number = decoder.next().ok_or_else(truncated)?;
+            *pos += decoder.bytes_consumed();
+            Ok(number)
+        }
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes)?;
+        let mut pos = 0;
+        let len = read_number(&bytes, &mut pos)?;
+        // Every entry takes at least three bytes. Don't trust a corrupt
+        // length with the allocation
+        let mut result = HashMap::with_capacity(cmp::min(len, bytes.len() / 3));
+        for _ in 0..len {
+            let term_len = read_number(&bytes, &mut pos)?;
+            let term = pos.checked_add(term_len)
+                .and_then(|end| bytes.get(pos..end))
+                .ok_or_else(truncated)?;
+            pos += term_len;
+            let term = TTerm::decode(term)?;
+            let term_id = read_number(&bytes, &mut pos)?;
+            result.insert(term, TermId(term_id as u64));
+        }
+        Ok(result)
+    }
+}
+
+impl<TTerm> PersistentVocabulary for SharedVocabulary<TTerm>
+    where TTerm: Hash + Eq + ByteEncodable + ByteDecodable
+{
+    fn save<W: Write>(&self, target: &mut W) -> io::Result<()> {
+        self.0.save(target)
+    }
+
+    fn load<R: Read>(source: &mut R) -> io::Result<Self> {
+        HashMap::load(source).map(SharedVocabulary)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io;
+
+    use super::{Vocabulary, PersistentVocabulary, SharedVocabulary, TermId};
+
+    #[test]
+    fn save_load() {
+        let mut vocabulary = HashMap::new();
+        for term in &["a", "", "perlin", "größe", "a", "z"] {
+            vocabulary.get_or_add(term.to_string());
+        }
+        // Ids don't have to be dense
+        vocabulary.insert("far".to_string(), TermId(1 << 30));
+        let mut bytes = Vec::new();
+        vocabulary.save(&mut bytes).unwrap();
+        let loaded = HashMap::<String, TermId>::load(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded, vocabulary);
+        assert_eq!(Vocabulary::get(&loaded, &"größe".to_string()), Some(TermId(3)));
+    }
+
+    #[test]
+    fn save_load_shared() {
+        let mut vocabulary = SharedVocabulary::new();
+        for i in 0..1000 {
+            vocabulary.get_or_add(format!("term{}", i));
+        }
+        let mut bytes = Vec::new();
+        vocabulary.save(&mut bytes).unwrap();
+        let mut loaded = SharedVocabulary::<String>::load(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.len(), 1000);
+        assert_eq!(loaded.get(&"term567".to_string()), Some(TermId(567)));
+        // New terms continue after the loaded ones
+        assert_eq!(loaded.get_or_add("new".to_string()), TermId(1000));
+    }
+
+    #[test]
+    fn save_load_numbers() {
+        let mut vocabulary = SharedVocabulary::new();
+        for i in 0..1000 {
+            vocabulary.get_or_add(i * 1000);
+        }
+        let mut bytes = Vec::new();
+        vocabulary.save(&mut bytes).unwrap();
+        let loaded = SharedVocabulary::<usize>::load(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.len(), 1000);
+        assert_eq!(loaded.get(&567_000), Some(TermId(567)));
+        // A term whose encoding does not end where its length says
+        let bytes = [0x81, 0x82, 0x81, 0x81, 0x80];
+        let err = HashMap::<usize, TermId>::load(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn load_truncated() {
+        let mut vocabulary = HashMap::new();
+        vocabulary.get_or_add("perlin".to_string());
+        let mut bytes = Vec::new();
+        vocabulary.save(&mut bytes).unwrap();
+        bytes.truncate(4);
+        let err = HashMap::<String, TermId>::load(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn load_corrupt_length() {
+        // Claims about 5 * 10^16 terms
+        let bytes = [0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0xff];
+        assert!(HashMap::<String, TermId>::load(&mut &bytes[..]).is_err());
+        // Does not fit into a usize at all
+        let bytes = [0x7f; 20].iter().cloned().chain(Some(0xff)).collect::<Vec<_>>();
+        assert!(HashMap::<String, TermId>::load(&mut bytes.as_slice()).is_err());
+    }
+}
@@ -5,6 +5,7 @@ use page_manager::PAGESIZE;
 pub const BLOCKSIZE: usize = 64;
 
 #[derive(Copy)]
+#[repr(C)]
 pub struct Block(pub [u8; BLOCKSIZE]);
 
 #[derive(Copy, Clone, Ord, PartialOrd, PartialEq, Eq, Debug)]
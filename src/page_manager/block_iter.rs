@@ -49,8 +49,19 @@ impl<'a> BlockIter<'a> {
         }
     }
 
+    /// Skips the next `by` blocks without reading them.
+    /// Shorthand for seeking relative to the current position
     pub fn skip_blocks(&mut self, by: usize) {
-        self.ptr += by;
+        let block_index = self.ptr + by;
+        self.seek_to_block(block_index);
+    }
+
+    /// Positions the iterator so that the next call to `next` returns the
+    /// block with this index in the listing. Seeking backwards is allowed.
+    /// Only the page holding that block is fetched, skipped blocks are never
+    /// read.
+    pub fn seek_to_block(&mut self, block_index: usize) {
+        self.ptr = block_index;
     }
 }
 
@@ -292,4 +303,30 @@ mod tests {
         assert_eq!(iter.next(), Some(Block([111; BLOCKSIZE])));
     }
 
+
+    #[test]
+    fn seek_to_block() {
+        let mut cache = new_cache("seek_to_block");
+        for i in 0..4 {
+            cache.store_block(Block([0; BLOCKSIZE]));
+            for j in 1..PAGESIZE {
+                cache.store_in_place(PageId(i),
+                                     BlockId(j as u16),
+                                     Block([((i as usize * PAGESIZE + j) % 255) as u8; BLOCKSIZE]));
+            }
+            cache.flush_page(PageId(i));
+        }
+        cache.store_block(Block([7; BLOCKSIZE]));
+        let unfull = cache.flush_unfull(PageId(4), BlockId(1));
+        let pages = Pages((0..4).map(PageId).collect::<Vec<_>>(), Some(unfull));
+        let linear = BlockIter::new(&cache, pages.clone()).collect::<Vec<_>>();
+        assert_eq!(linear.len(), 4 * PAGESIZE + 1);
+        let mut iter = BlockIter::new(&cache, pages);
+        for &target in &[PAGESIZE * 2 + 5, 3, PAGESIZE * 4, PAGESIZE - 1] {
+            iter.seek_to_block(target);
+            assert_eq!(iter.clone().collect::<Vec<_>>(), &linear[target..]);
+        }
+        iter.seek_to_block(PAGESIZE * 4 + 1);
+        assert_eq!(iter.next(), None);
+    }
 }
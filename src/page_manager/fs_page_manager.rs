@@ -1,6 +1,7 @@
 use std::path::Path;
 use std::io::{Seek, SeekFrom, Write, Read};
 use std::fs::{OpenOptions, File};
+use std::sync::Mutex;
 
 use utils::counter::Counter;
 use page_manager::{UnfullPage, Page, PageId, BlockId, PageStore, PAGESIZE, BLOCKSIZE};
@@ -8,33 +9,65 @@ use page_manager::{UnfullPage, Page, PageId, BlockId, PageStore, PAGESIZE, BLOCK
 #[derive(Debug)]
 pub struct FsPageManager {
     pages: File,
+    // Cloned file handles share their offset. Concurrent readers have to
+    // seek and read one after another
+    reader: Mutex<File>,
     count: Counter,
     last_page_last_block: BlockId,
     unpopulated_pages: Vec<PageId>,
+    durable: bool,
 }
 
 impl FsPageManager {
     pub fn new(path: &Path) -> Self {
+        let pages = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
         FsPageManager {
-            pages: OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(path)
-                .unwrap(),
+            reader: Mutex::new(pages.try_clone().unwrap()),
+            pages,
             count: Counter::new(),
             last_page_last_block: BlockId(PAGESIZE as u16),
             unpopulated_pages: Vec::new(),
+            durable: false,
         }
     }
 
+    /// Pre-extends the page file to hold at least `pages` pages.
+    /// Use this before bulk loads to reduce fragmentation of the file.
+    pub fn reserve(&mut self, pages: usize) {
+        let len = pages as u64 * PAGESIZE as u64 * BLOCKSIZE as u64;
+        if self.pages.metadata().unwrap().len() < len {
+            self.pages.set_len(len).unwrap();
+        }
+    }
+
+    /// If set, every write to the page file is synced to disk before
+    /// returning. This survives power loss but costs an fsync per written
+    /// page, which slows down indexing considerably.
+    /// For most cases calling `sync` after a commit is the better trade-off.
+    pub fn set_durable(&mut self, durable: bool) {
+        self.durable = durable;
+    }
+
+    /// Flushes all written pages from the OS cache to disk.
+    pub fn sync(&self) {
+        self.pages.sync_all().unwrap();
+    }
+
     //TODO: Think about solving this with write_at in https://doc.rust-lang.org/std/os/unix/fs/trait.FileExt.html
     fn write_page(&mut self, page: Page, page_id: PageId) {
         let id = page_id.0;
         let mut f = self.pages.try_clone().unwrap();
         f.seek(SeekFrom::Start(id * PAGESIZE as u64 * BLOCKSIZE as u64)).unwrap();
         f.write_all(page.as_slice()).unwrap();
+        if self.durable {
+            f.sync_data().unwrap();
+        }
         self.last_page_last_block = BlockId(PAGESIZE as u16);
     }
 }
@@ -92,18 +125,25 @@ impl PageStore for FsPageManager {
         //If its zero it means no more relevant data is on that page
         //Throw it into the unpopulated pages
         if refcount[0] == 0 {
+            // Unfull pages must not be appended to a page that might be reused
+            if self.count.retrieve() == page_id.0 + 1 {
+                self.last_page_last_block = BlockId(PAGESIZE as u16);
+            }
             self.unpopulated_pages.push(page_id);
         } else {
             //Otherwise we have to write the refcount back to page... alas
             f.seek(SeekFrom::Start(page_id.0 * PAGESIZE as u64 * BLOCKSIZE as u64)).unwrap();
             f.write_all(&refcount).unwrap();
+            if self.durable {
+                f.sync_data().unwrap();
+            }
         }
     }
 
     fn get_page(&self, page_id: PageId) -> Page {
-        let mut f = self.pages.try_clone().unwrap();
+        let mut f = self.reader.lock().unwrap();
         f.seek(SeekFrom::Start(page_id.0 * PAGESIZE as u64 * BLOCKSIZE as u64)).unwrap();
-        Page::from_read(&mut f)
+        Page::from_read(&mut *f)
     }
 }
 
@@ -119,6 +159,25 @@ mod tests {
         FsPageManager::new(&path.join("pages.bin"))
     }
 
+    #[test]
+    fn durable() {
+        let path = &create_test_dir("fs_page_manager/durable");
+        let mut pmgr = FsPageManager::new(&path.join("pages.bin"));
+        pmgr.set_durable(true);
+        let mut p = Page::empty();
+        p[BlockId::first()] = Block([7; BLOCKSIZE]);
+        assert_eq!(pmgr.store_full(p), PageId(0));
+        assert_eq!(pmgr.store_unfull(p, BlockId(1)),
+                   UnfullPage::new(PageId(1), BlockId(1), BlockId(2)));
+        pmgr.set_durable(false);
+        assert_eq!(pmgr.store_full(p), PageId(2));
+        pmgr.sync();
+        assert_eq!(pmgr.get_page(PageId(0)), p);
+        assert_eq!(pmgr.get_page(PageId(2)), p);
+        assert_eq!(path.join("pages.bin").metadata().unwrap().len(),
+                   3 * (PAGESIZE * BLOCKSIZE) as u64);
+    }
+
     #[test]
     fn delete_unfull_basic() {
         let mut pmgr = new_pmgr("delete_unfull_basic");
@@ -129,6 +188,18 @@ mod tests {
         assert_eq!(pmgr.store_full(Page::empty()), PageId(0));
     }
 
+    #[test]
+    fn delete_last_unfull() {
+        let mut pmgr = new_pmgr("delete_last_unfull");
+        assert_eq!(pmgr.store_unfull(Page::empty(), BlockId(1)),
+                   UnfullPage::new(PageId(0), BlockId(1), BlockId(2)));
+        pmgr.delete_unfull(PageId(0));
+        // The emptied page is reused for full pages only
+        assert_eq!(pmgr.store_unfull(Page::empty(), BlockId(1)),
+                   UnfullPage::new(PageId(1), BlockId(1), BlockId(2)));
+        assert_eq!(pmgr.store_full(Page::empty()), PageId(0));
+    }
+
     #[test]
     fn delete_multitenant_unfull() {
         let mut pmgr = new_pmgr("delete_multitenant_unfull");
@@ -268,6 +339,28 @@ mod tests {
         assert_eq!(pmgr.get_page(PageId(1)), p);
     }
 
+    #[test]
+    fn reserve() {
+        let mut pmgr = new_pmgr("reserve");
+        let mut reserved = new_pmgr("reserve_reserved");
+        reserved.reserve(100);
+        assert_eq!(reserved.pages.metadata().unwrap().len(),
+                   100 * (PAGESIZE * BLOCKSIZE) as u64);
+        for i in 0..10 {
+            let mut p = Page::empty();
+            p[BlockId::first()] = Block([i as u8; BLOCKSIZE]);
+            assert_eq!(pmgr.store_full(p), reserved.store_full(p));
+            assert_eq!(pmgr.store_unfull(p, BlockId(1)), reserved.store_unfull(p, BlockId(1)));
+        }
+        for i in 0..20 {
+            assert_eq!(pmgr.get_page(PageId(i)), reserved.get_page(PageId(i)));
+        }
+        // Reserving less than what is there does not truncate
+        reserved.reserve(1);
+        assert_eq!(reserved.pages.metadata().unwrap().len(),
+                   100 * (PAGESIZE * BLOCKSIZE) as u64);
+    }
+
     #[test]
     fn combined() {
         let path = &create_test_dir("fs_page_manager/combined");
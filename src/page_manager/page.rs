@@ -10,8 +10,15 @@ use page_manager:: {BLOCKSIZE, Block, BlockId};
 pub const PAGESIZE: usize = 64;
 
 #[derive(Copy)]
+#[repr(C)]
 pub struct Page(pub [Block; PAGESIZE]);
 
+// `Page::as_slice` and `Page::from_read` reinterpret a page as its raw bytes.
+// This is only sound as long as a page is exactly BLOCKSIZE*PAGESIZE bytes
+// without any padding or alignment requirements.
+const _: () = assert!(mem::size_of::<Page>() == BLOCKSIZE * PAGESIZE);
+const _: () = assert!(mem::align_of::<Page>() == 1);
+
 #[derive(Copy, Clone, Ord, PartialOrd, PartialEq, Eq, Debug)]
 pub struct PageId(pub u64);
 
@@ -111,7 +118,7 @@ impl Page {
     }
 
     pub fn from_read<R: io::Read>(source: &mut R) -> Page {
-        let mut raw: [u8; BLOCKSIZE*PAGESIZE] = unsafe {mem::uninitialized()};
+        let mut raw = [0u8; BLOCKSIZE*PAGESIZE];
         source.read_exact(&mut raw).unwrap();
         unsafe {mem::transmute(raw)}
     }
@@ -150,3 +157,31 @@ impl IndexMut<BlockId> for Page {
         &mut self.0[_index.0 as usize]
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::{Page, PAGESIZE};
+    use page_manager::{Block, BlockId, BLOCKSIZE};
+
+    #[test]
+    fn layout() {
+        assert_eq!(mem::size_of::<Page>(), BLOCKSIZE * PAGESIZE);
+        assert_eq!(mem::align_of::<Page>(), 1);
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        let mut page = Page::empty();
+        page[BlockId::first()] = Block([1; BLOCKSIZE]);
+        page[BlockId::last()] = Block([2; BLOCKSIZE]);
+        let bytes = page.as_slice();
+        assert_eq!(bytes.len(), BLOCKSIZE * PAGESIZE);
+        assert_eq!(bytes[0], 1);
+        assert_eq!(bytes[BLOCKSIZE], 0);
+        assert_eq!(bytes[BLOCKSIZE * PAGESIZE - 1], 2);
+        assert_eq!(Page::from_read(&mut &bytes[..]), page);
+    }
+}
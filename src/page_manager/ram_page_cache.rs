@@ -1,15 +1,21 @@
+use std::mem;
 use std::collections::BTreeMap;
 use std::sync::{RwLock, Arc};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use utils::counter::Counter;
 use page_manager::{FsPageManager, UnfullPage, Page, Block, BlockManager, PageStore, PageId,
                    BlockId, PageCache};
 
+/// Default number of pages kept in the read cache
 const CACHESIZE: usize = 16;
 
 #[derive(Debug)]
 pub struct RamPageCache {
-    cache: RwLock<Vec<(PageId, Arc<Page>)>>,
+    /// Sorted by PageId. The last field is the tick of the last access
+    cache: RwLock<Vec<(PageId, Arc<Page>, AtomicUsize)>>,
+    capacity: usize,
+    clock: AtomicUsize,
     counter: Counter,
     construction_cache: BTreeMap<PageId, Page>,
     store: FsPageManager,
@@ -17,17 +23,41 @@ pub struct RamPageCache {
 
 impl RamPageCache {
     pub fn new(store: FsPageManager) -> Self {
+        RamPageCache::with_capacity(store, CACHESIZE)
+    }
+
+    /// Cache that keeps at most `capacity` pages for reading and evicts the
+    /// least recently used one when full. 0 disables read caching.
+    /// Pages under construction are not counted. There is only one per
+    /// listing and they can't be evicted before they are flushed.
+    pub fn with_capacity(store: FsPageManager, capacity: usize) -> Self {
         RamPageCache {
             counter: Counter::new(),
-            cache: RwLock::new(Vec::with_capacity(CACHESIZE)),
+            cache: RwLock::new(Vec::with_capacity(capacity)),
+            capacity,
+            clock: AtomicUsize::new(0),
             construction_cache: BTreeMap::new(),
             store: store,
         }
     }
 
+    /// Approximate number of bytes held in memory by cached pages and pages
+    /// under construction
+    pub fn memory_usage(&self) -> usize {
+        let cached = self.cache.read().unwrap().len();
+        let constructing = self.construction_cache.len();
+        (cached + constructing) * mem::size_of::<Page>()
+    }
+
+    /// Flushes all pages written to the underlying store to disk.
+    /// Pages still under construction are not affected.
+    pub fn sync(&self) {
+        self.store.sync();
+    }
+
     #[inline]
     fn search_page(&self, page_id: &PageId) -> Result<usize, usize> {
-        self.cache.read().unwrap().binary_search_by_key(page_id, |&(pid, _)| pid)
+        self.cache.read().unwrap().binary_search_by_key(page_id, |&(pid, _, _)| pid)
     }
 
     fn invalidate(&mut self, page_id: PageId) {
@@ -60,8 +90,11 @@ impl BlockManager for RamPageCache {
     fn flush_page(&mut self, page_id: PageId) -> PageId {
         if let Some(page) = self.construction_cache.remove(&page_id)
         {
-            self.invalidate(page_id);
-            return self.store.store_full(page);
+            // The store decides where the page ends up. That page might be
+            // cached with its previous content
+            let stored_id = self.store.store_full(page);
+            self.invalidate(stored_id);
+            return stored_id;
         }
         unreachable!();
         // If page is not in cache it needs not to be flushed
@@ -70,8 +103,10 @@ impl BlockManager for RamPageCache {
     fn flush_unfull(&mut self, page_id: PageId, block_id: BlockId) -> UnfullPage {
         if let Some(page) = self.construction_cache.remove(&page_id)
         {
-            self.invalidate(page_id);
-            return self.store.store_unfull(page, block_id);
+            // Unfull pages share a page on disk. It might be cached already
+            let unfull_page = self.store.store_unfull(page, block_id);
+            self.invalidate(unfull_page.page_id());
+            return unfull_page;
         }
         unreachable!();
         // If page is not in cache it needs not to be flushed
@@ -90,26 +125,38 @@ impl PageCache for RamPageCache {
     }
 
     fn get_page(&self, page_id: PageId) -> Arc<Page> {
-        use std::cmp;
-        match self.search_page(&page_id) {
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed);
+        {
+            let cache = self.cache.read().unwrap();
             // Page in cache
-            Ok(index) => self.cache.read().unwrap()[index].1.clone(),
-            // Page not in cache
-            Err(index) => {
-                // Get it, arc it
-                let page = Arc::new(self.store.get_page(page_id));
-                // If cache is not full
-                if self.cache.read().unwrap().len() < CACHESIZE {
-                    // Insert it
-                    self.cache.write().unwrap().insert(index, (page_id, page.clone()));
-                } else {
-                    // Otherwise replace it
-                    let index = cmp::min(index, CACHESIZE - 1);
-                    self.cache.write().unwrap()[index] = (page_id, page.clone());
+            if let Ok(index) = cache.binary_search_by_key(&page_id, |&(pid, _, _)| pid) {
+                cache[index].2.store(tick, Ordering::Relaxed);
+                return cache[index].1.clone();
+            }
+        }
+        // Page not in cache. Get it, arc it
+        let page = Arc::new(self.store.get_page(page_id));
+        if self.capacity == 0 {
+            return page;
+        }
+        let mut cache = self.cache.write().unwrap();
+        // Another reader might have changed the cache in the meantime.
+        // Search again while holding the write lock
+        if let Err(mut index) = cache.binary_search_by_key(&page_id, |&(pid, _, _)| pid) {
+            if cache.len() >= self.capacity {
+                // Evict the least recently used page. Iterators still holding
+                // it keep their Arc, so this is safe in the middle of a scan
+                let lru = (0..cache.len())
+                    .min_by_key(|&i| cache[i].2.load(Ordering::Relaxed))
+                    .unwrap();
+                cache.remove(lru);
+                if lru < index {
+                    index -= 1;
                 }
-                page
             }
+            cache.insert(index, (page_id, page.clone(), AtomicUsize::new(tick)));
         }
+        page
     }
 }
 
@@ -192,4 +239,23 @@ mod tests {
             assert_eq!(cache.get_page(PageId(i)), Arc::new(p));
         }
     }
+
+    #[test]
+    fn lru() {
+        let path = &create_test_dir("ram_page_cache/lru");
+        let mut cache = RamPageCache::with_capacity(FsPageManager::new(&path.join("pages.bin")), 2);
+        for i in 0..3 {
+            cache.store_block(Block([i as u8; BLOCKSIZE]));
+            cache.flush_page(PageId(i));
+        }
+        let p0 = cache.get_page(PageId(0));
+        cache.get_page(PageId(1));
+        cache.get_page(PageId(0));
+        // Evicts page 1, which was used least recently
+        cache.get_page(PageId(2));
+        assert!(Arc::ptr_eq(&p0, &cache.get_page(PageId(0))));
+        assert_eq!(cache.cache.read().unwrap().iter().map(|e| e.0).collect::<Vec<_>>(),
+                   vec![PageId(0), PageId(2)]);
+        assert_eq!(cache.get_page(PageId(1))[BlockId::first()], Block([1; BLOCKSIZE]));
+    }
 }
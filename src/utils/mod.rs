@@ -2,6 +2,7 @@ pub mod progress;
 pub mod seeking_iterator;
 pub mod counter;
 pub mod ring_buffer;
+pub mod vbyte;
 
 pub trait Baseable<T> {
     fn add_base(&mut self, T);
@@ -3,16 +3,19 @@ use std::mem;
 use std::ops::{DerefMut, Deref};
 use utils::Baseable;
 
-const SIZE: usize = 64;
+/// Default capacity of a ring buffer
+pub const SIZE: usize = 64;
 
+/// Fixed capacity FIFO queue. The capacity `N` does not need to be a power
+/// of two
 #[derive(Copy)]
-pub struct RingBuffer<T> {
-    buff: [T; SIZE],
+pub struct RingBuffer<T, const N: usize = SIZE> {
+    buff: [T; N],
     start: usize,
     count: usize,
 }
 
-impl<T: fmt::Debug> fmt::Debug for RingBuffer<T> {
+impl<T: fmt::Debug, const N: usize> fmt::Debug for RingBuffer<T, N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f,
                  "RingBuffer: {:?}, start: {}, count {}",
@@ -22,20 +25,20 @@ impl<T: fmt::Debug> fmt::Debug for RingBuffer<T> {
     }
 }
 
-impl<T: Copy + Clone> Clone for RingBuffer<T> {
-    fn clone(&self) -> RingBuffer<T> {
+impl<T: Copy + Clone, const N: usize> Clone for RingBuffer<T, N> {
+    fn clone(&self) -> RingBuffer<T, N> {
         *self
     }
 }
 
 #[derive(Debug)]
-pub struct BiasedRingBuffer<T> {
-    buff: RingBuffer<T>,
+pub struct BiasedRingBuffer<T, const N: usize = SIZE> {
+    buff: RingBuffer<T, N>,
     base: T,
 }
 
-impl<T: Copy> Clone for BiasedRingBuffer<T> {
-    fn clone(&self) -> BiasedRingBuffer<T> {
+impl<T: Copy, const N: usize> Clone for BiasedRingBuffer<T, N> {
+    fn clone(&self) -> BiasedRingBuffer<T, N> {
         BiasedRingBuffer {
             buff: self.buff.clone(),
             base: self.base,
@@ -47,12 +50,24 @@ impl<T> BiasedRingBuffer<T>
     where T: for<'x> Baseable<&'x T> + Default + Copy
 {
     pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T, const N: usize> Default for BiasedRingBuffer<T, N>
+    where T: for<'x> Baseable<&'x T> + Default + Copy
+{
+    fn default() -> Self {
         BiasedRingBuffer {
-            buff: RingBuffer::new(),
+            buff: RingBuffer::default(),
             base: T::default(),
         }
     }
+}
 
+impl<T, const N: usize> BiasedRingBuffer<T, N>
+    where T: for<'x> Baseable<&'x T> + Default + Copy
+{
     pub fn pop_front_biased(&mut self) -> Option<T> {
         self.buff.pop_front().map(|mut e| {
                                       e.sub_base(&self.base);
@@ -68,42 +83,62 @@ impl<T> BiasedRingBuffer<T>
     pub fn set_base(&mut self, base: T) {
         self.base = base;
     }
+
+    /// Iterates like `pop_front_biased` would, without removing anything
+    pub fn iter_biased<'a>(&'a self) -> impl Iterator<Item = T> + 'a {
+        let base = &self.base;
+        self.buff.iter().map(move |element| {
+            let mut element = *element;
+            element.sub_base(base);
+            element
+        })
+    }
 }
 
-impl<T> DerefMut for BiasedRingBuffer<T> {
-    fn deref_mut(&mut self) -> &mut RingBuffer<T> {
+impl<T, const N: usize> DerefMut for BiasedRingBuffer<T, N> {
+    fn deref_mut(&mut self) -> &mut RingBuffer<T, N> {
         &mut self.buff
     }
 }
 
-impl<T> Deref for BiasedRingBuffer<T> {
-    type Target = RingBuffer<T>;
-    fn deref(&self) -> &RingBuffer<T> {
+impl<T, const N: usize> Deref for BiasedRingBuffer<T, N> {
+    type Target = RingBuffer<T, N>;
+    fn deref(&self) -> &RingBuffer<T, N> {
         &self.buff
     }
 }
 
-impl<T> AsRef<RingBuffer<T>> for BiasedRingBuffer<T> {
-    fn as_ref(&self) -> &RingBuffer<T> {
+impl<T, const N: usize> AsRef<RingBuffer<T, N>> for BiasedRingBuffer<T, N> {
+    fn as_ref(&self) -> &RingBuffer<T, N> {
         &self.buff
     }
 }
 
-impl<T> AsMut<RingBuffer<T>> for BiasedRingBuffer<T> {
-    fn as_mut(&mut self) -> &mut RingBuffer<T> {
+impl<T, const N: usize> AsMut<RingBuffer<T, N>> for BiasedRingBuffer<T, N> {
+    fn as_mut(&mut self) -> &mut RingBuffer<T, N> {
         &mut self.buff
     }
 }
 
 impl<T: Copy> RingBuffer<T> {
+    /// Ring buffer with the default capacity.
+    /// Use `RingBuffer::<T, N>::default()` for other capacities
     pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
         RingBuffer {
             buff: unsafe { mem::uninitialized() },
             start: 0,
             count: 0,
         }
     }
+}
 
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
     #[inline]
     pub fn flush(&mut self) {
         self.start = 0;
@@ -111,8 +146,8 @@ impl<T: Copy> RingBuffer<T> {
     }
 
     pub fn push_back(&mut self, element: T) {
-        debug_assert!(self.count < SIZE);
-        self.buff[(self.start + self.count) % SIZE] = element;
+        debug_assert!(self.count < N);
+        self.buff[(self.start + self.count) % N] = element;
         self.count += 1;
     }
 
@@ -121,13 +156,18 @@ impl<T: Copy> RingBuffer<T> {
             let element = Some(self.buff[self.start]);
             self.count -= 1;
             self.start += 1;
-            self.start %= SIZE;
+            self.start %= N;
             element
         } else {
             None
         }
     }
 
+    /// Iterates from front to back without removing anything
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T> + 'a {
+        (0..self.count).map(move |i| &self.buff[(self.start + i) % N])
+    }
+
     pub fn peek_front(&self) -> Option<&T> {
         if self.count > 0 {
             Some(&self.buff[self.start])
@@ -145,12 +185,18 @@ impl<T: Copy> RingBuffer<T> {
     pub fn is_empty(&self) -> bool {
         self.count == 0
     }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use super::{SIZE, RingBuffer};
+    use super::{SIZE, RingBuffer, BiasedRingBuffer};
+    use index::posting::{Posting, DocId};
 
     #[test]
     fn creating() {
@@ -209,6 +255,42 @@ mod tests {
         assert_eq!(buffer.count(), SIZE - 2);
     }
 
+    #[test]
+    fn odd_capacity() {
+        let mut buffer = RingBuffer::<usize, 5>::default();
+        assert_eq!(buffer.capacity(), 5);
+        // Wrap around the end of the buffer several times
+        for i in 0..23 {
+            buffer.push_back(i);
+            buffer.push_back(i + 100);
+            assert_eq!(buffer.pop_front(), Some(if i == 0 { 0 } else { i + 99 }));
+            if i > 0 {
+                assert_eq!(buffer.pop_front(), Some(i));
+            }
+        }
+        assert_eq!(buffer.pop_front(), Some(122));
+        assert!(buffer.is_empty());
+        for i in 0..5 {
+            buffer.push_back(i);
+        }
+        assert_eq!(buffer.count(), 5);
+        for i in 0..5 {
+            assert_eq!(buffer.pop_front(), Some(i));
+        }
+    }
+
+    #[test]
+    fn biased_capacity() {
+        let mut buffer = BiasedRingBuffer::<Posting, 3>::default();
+        assert_eq!(buffer.capacity(), 3);
+        buffer.set_base(Posting(DocId(10)));
+        buffer.push_back(Posting(DocId(15)));
+        buffer.push_back(Posting(DocId(17)));
+        assert_eq!(buffer.pop_front_biased(), Some(Posting(DocId(5))));
+        assert_eq!(buffer.pop_front_biased(), Some(Posting(DocId(7))));
+        assert_eq!(BiasedRingBuffer::<Posting>::new().capacity(), SIZE);
+    }
+
     #[test]
     fn empty() {
         let mut buffer = RingBuffer::<usize>::new();
@@ -227,4 +309,40 @@ mod tests {
         assert_eq!(buffer.pop_front(), None);
         assert!(buffer.is_empty());
     }
+
+    #[test]
+    fn iter() {
+        let mut buffer = RingBuffer::<u64, 5>::default();
+        assert_eq!(buffer.iter().next(), None);
+        for i in 0..4 {
+            buffer.push_back(i);
+        }
+        buffer.pop_front();
+        buffer.pop_front();
+        // Wraps around the end of the underlying array
+        buffer.push_back(4);
+        buffer.push_back(5);
+        buffer.push_back(6);
+        assert_eq!(buffer.iter().cloned().collect::<Vec<_>>(), vec![2, 3, 4, 5, 6]);
+        assert_eq!(buffer.count(), 5);
+        assert_eq!(buffer.pop_front(), Some(2));
+    }
+
+    #[test]
+    fn iter_biased() {
+        let mut buffer = BiasedRingBuffer::<Posting>::new();
+        for i in 0..SIZE as u32 - 2 {
+            buffer.push_back(Posting(DocId(i)));
+            buffer.pop_front();
+        }
+        buffer.set_base(Posting(DocId(100)));
+        for i in 0..5 {
+            buffer.push_back_biased(Posting(DocId(i * 10)));
+        }
+        assert_eq!(buffer.iter_biased().collect::<Vec<_>>(),
+                   (0..5).map(|i| Posting(DocId(i * 10))).collect::<Vec<_>>());
+        assert_eq!(buffer.iter().next(), Some(&Posting(DocId(100))));
+        assert_eq!(buffer.count(), 5);
+        assert_eq!(buffer.pop_front_biased(), Some(Posting(DocId(0))));
+    }
 }
@@ -0,0 +1,232 @@
+//! Variable byte encoding of unsigned integers.
+//!
+//! Every byte carries seven bits of the number, most significant group first.
+//! The highest bit marks the last byte of a number.
+
+use std::convert::TryFrom;
+
+/// Encodes a number as variable byte
+pub fn vbyte_encode(mut number: usize) -> Vec<u8> {
+    let mut result = Vec::with_capacity(vbyte_len(number));
+    loop {
+        result.insert(0, (number % 128) as u8);
+        if number < 128 {
+            break;
+        }
+        number /= 128;
+    }
+    let last = result.len() - 1;
+    result[last] += 128;
+    result
+}
+
+/// Number of bytes `vbyte_encode` needs for this number
+pub fn vbyte_len(mut number: usize) -> usize {
+    let mut len = 1;
+    while number >= 128 {
+        number /= 128;
+        len += 1;
+    }
+    len
+}
+
+/// Decodes variable byte encoded numbers from an iterator over bytes
+pub struct VByteDecoder<T: Iterator<Item = u8>> {
+    bytes: T,
+    consumed: usize,
+    /// Value decoded by `peek` and the number of bytes it took
+    peeked: Option<(usize, usize)>,
+}
+
+impl<T: Iterator<Item = u8>> VByteDecoder<T> {
+    pub fn new(bytes: T) -> Self {
+        VByteDecoder {
+            bytes,
+            consumed: 0,
+            peeked: None,
+        }
+    }
+
+    /// Access the bytes that were not decoded yet.
+    /// Bytes of a peeked value were already taken from it. Bytes taken
+    /// directly are not counted by `bytes_consumed`.
+    pub fn underlying_iterator(&mut self) -> &mut T {
+        &mut self.bytes
+    }
+
+    /// Returns the next number without advancing past it
+    pub fn peek(&mut self) -> Option<usize> {
+        if self.peeked.is_none() {
+            self.peeked = self.decode();
+        }
+        self.peeked.map(|(value, _)| value)
+    }
+
+    /// Number of bytes of the numbers returned by `next` so far
+    pub fn bytes_consumed(&self) -> usize {
+        self.consumed
+    }
+
+    fn decode(&mut self) -> Option<(usize, usize)> {
+        let mut result: usize = 0;
+        let mut len = 0;
+        loop {
+            let byte = self.bytes.next()?;
+            len += 1;
+            let group = (byte & 127) as usize;
+            result = result.checked_mul(128)?.checked_add(group)?;
+            if byte >= 128 {
+                return Some((result, len));
+            }
+        }
+    }
+}
+
+impl<T: Iterator<Item = u8>> Iterator for VByteDecoder<T> {
+    type Item = usize;
+
+    /// Returns `None` if the bytes end, even in the middle of a number, or
+    /// if the number does not fit into a `usize`
+    fn next(&mut self) -> Option<usize> {
+        let (value, len) = self.peeked.take().or_else(|| self.decode())?;
+        self.consumed += len;
+        Some(value)
+    }
+}
+
+/// Encodes sorted, ascending ids as variable byte deltas.
+/// Dense result sets need about one byte per id.
+///
+/// # Panics
+/// If `ids` is not sorted ascending, or if the gap between two ids does not
+/// fit into a `usize` (only possible on platforms with less than 64 bits).
+pub fn serialize_results(ids: &[u64]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(ids.len());
+    let mut last = 0;
+    for &id in ids {
+        let delta = id.checked_sub(last)
+            .unwrap_or_else(|| panic!("ids must be sorted ascending, but {} follows {}", id, last));
+        let delta = usize::try_from(delta)
+            .unwrap_or_else(|_| panic!("gap of {} between ids does not fit into usize", delta));
+        result.extend(vbyte_encode(delta));
+        last = id;
+    }
+    result
+}
+
+/// Decodes ids encoded by `serialize_results`
+pub fn deserialize_results(bytes: &[u8]) -> Vec<u64> {
+    let mut last = 0;
+    VByteDecoder::new(bytes.iter().cloned())
+        .map(|delta| {
+            last += delta as u64;
+            last
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{vbyte_encode, vbyte_len, VByteDecoder, serialize_results, deserialize_results};
+
+    #[test]
+    fn encode() {
+        assert_eq!(vbyte_encode(0), vec![128]);
+        assert_eq!(vbyte_encode(5), vec![133]);
+        assert_eq!(vbyte_encode(127), vec![255]);
+        assert_eq!(vbyte_encode(128), vec![1, 128]);
+        assert_eq!(vbyte_encode(130), vec![1, 130]);
+        assert_eq!(vbyte_encode(16384), vec![1, 0, 128]);
+    }
+
+    #[test]
+    fn len() {
+        for &number in &[0, 1, 127, 128, 16383, 16384, u32::MAX as usize] {
+            assert_eq!(vbyte_len(number), vbyte_encode(number).len());
+        }
+    }
+
+    #[test]
+    fn decode() {
+        let numbers = vec![0, 1, 127, 128, 300, 16384, u32::MAX as usize];
+        let bytes = numbers.iter().flat_map(|number| vbyte_encode(*number)).collect::<Vec<_>>();
+        let decoded = VByteDecoder::new(bytes.into_iter()).collect::<Vec<_>>();
+        assert_eq!(decoded, numbers);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let mut bytes = vbyte_encode(5);
+        bytes.extend(vbyte_encode(16384));
+        bytes.pop();
+        let mut decoder = VByteDecoder::new(bytes.into_iter());
+        assert_eq!(decoder.next(), Some(5));
+        assert_eq!(decoder.next(), None);
+    }
+
+    #[test]
+    fn decode_overflow() {
+        let mut bytes = vec![127; 12];
+        bytes.push(255);
+        assert_eq!(VByteDecoder::new(bytes.into_iter()).next(), None);
+        let max = vbyte_encode(usize::MAX);
+        assert_eq!(VByteDecoder::new(max.into_iter()).next(), Some(usize::MAX));
+    }
+
+    #[test]
+    fn peek() {
+        let mut bytes = vbyte_encode(300);
+        bytes.extend(vbyte_encode(7));
+        let mut decoder = VByteDecoder::new(bytes.into_iter());
+        assert_eq!(decoder.peek(), Some(300));
+        assert_eq!(decoder.peek(), Some(300));
+        assert_eq!(decoder.bytes_consumed(), 0);
+        assert_eq!(decoder.next(), Some(300));
+        assert_eq!(decoder.next(), Some(7));
+        assert_eq!(decoder.peek(), None);
+        assert_eq!(decoder.next(), None);
+    }
+
+    #[test]
+    fn bytes_consumed() {
+        let numbers = [5, 128, 16384, u32::MAX as usize, 0];
+        let bytes = numbers.iter().flat_map(|number| vbyte_encode(*number)).collect::<Vec<_>>();
+        let mut decoder = VByteDecoder::new(bytes.iter().cloned());
+        let mut expected = 0;
+        for &number in &numbers {
+            assert_eq!(decoder.bytes_consumed(), expected);
+            decoder.peek();
+            assert_eq!(decoder.bytes_consumed(), expected);
+            assert_eq!(decoder.next(), Some(number));
+            expected += vbyte_len(number);
+        }
+        assert_eq!(decoder.bytes_consumed(), bytes.len());
+        // A truncated number is not counted
+        let mut decoder = VByteDecoder::new(vec![1, 0].into_iter());
+        assert_eq!(decoder.next(), None);
+        assert_eq!(decoder.bytes_consumed(), 0);
+    }
+
+    #[test]
+    fn results_roundtrip() {
+        let ids = vec![0, 3, 4, 200, 1 << 40, (1 << 40) + 1];
+        assert_eq!(deserialize_results(&serialize_results(&ids)), ids);
+        assert_eq!(deserialize_results(&serialize_results(&[])), Vec::<u64>::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted ascending")]
+    fn results_unsorted() {
+        serialize_results(&[1, 5, 3]);
+    }
+
+    #[test]
+    fn results_dense() {
+        let ids = (1000..11000).map(|i| i * 3).collect::<Vec<u64>>();
+        let bytes = serialize_results(&ids);
+        // Only the first delta needs more than one byte, far below 8 bytes per id
+        assert_eq!(bytes.len(), ids.len() + 1);
+        assert_eq!(deserialize_results(&bytes), ids);
+    }
+}